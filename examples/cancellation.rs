@@ -0,0 +1,50 @@
+//! An example of cancelling a streaming chat completion.
+
+// You can execute this example with `cargo run --example cancellation`
+
+use futures_util::stream::StreamExt;
+use std::io::Write;
+use std::time::Duration;
+use transformrs::chat;
+use transformrs::Message;
+use transformrs::Provider;
+
+#[tokio::main]
+async fn main() {
+    let messages = vec![
+        Message::system("You are a helpful assistant."),
+        Message::user("Give a detailed history of the internet."),
+    ];
+    let keys = transformrs::load_keys(".env");
+    let provider = Provider::DeepInfra;
+    let key = keys.for_provider(&provider).unwrap();
+    let model = "meta-llama/Llama-3.3-70B-Instruct";
+    let mut stream = chat::stream_chat_completion(&provider, &key, model, &messages)
+        .await
+        .unwrap();
+    // Stands in for the user pressing "stop", for example a message on a
+    // channel from the UI.
+    let stop = tokio::time::sleep(Duration::from_secs(2));
+    tokio::pin!(stop);
+    loop {
+        tokio::select! {
+            resp = stream.next() => match resp {
+                Some(resp) => {
+                    print!(
+                        "{}",
+                        resp.choices[0].delta.content.clone().unwrap_or_default()
+                    );
+                    std::io::stdout().flush().unwrap();
+                }
+                None => break,
+            },
+            _ = &mut stop => {
+                println!("\n\nStopped by the user.");
+                break;
+            }
+        }
+    }
+    // Dropping the stream closes the connection, so the provider stops
+    // generating tokens.
+    drop(stream);
+}
@@ -22,8 +22,8 @@ pub fn init_subscriber(level: tracing::Level) -> Result<(), SetGlobalDefaultErro
 async fn main() {
     init_subscriber(tracing::Level::DEBUG).unwrap();
     let messages = vec![
-        Message::from_str("system", "You are a helpful assistant."),
-        Message::from_str("user", "This is a test. Please respond with 'hello world'."),
+        Message::system("You are a helpful assistant."),
+        Message::user("This is a test. Please respond with 'hello world'."),
     ];
     let keys = transformrs::load_keys(".env");
     let provider = Provider::DeepInfra;
@@ -34,7 +34,7 @@ async fn main() {
         .unwrap()
         .structured()
         .unwrap();
-    println!("{}", resp.choices[0].message.content);
+    println!("{}", resp.first_text().unwrap());
 }
 
 // output:
@@ -5,10 +5,10 @@ use transformrs::Provider;
 #[tokio::main]
 async fn main() {
     let messages = vec![
-        Message::from_str("user", "Describe this image in one short sentence."),
+        Message::user("Describe this image in one short sentence."),
         // To pass a local image, use `Message::from_image_bytes`, for example:
-        // Message::from_image_bytes("user", "jpeg", include_bytes!("sunset.jpg")),
-        Message::from_image_url("user", "https://transformrs.org/sunset.jpg"),
+        // Message::from_image_bytes("user", "jpeg", include_bytes!("sunset.jpg")).unwrap(),
+        Message::from_image_url("user", "https://transformrs.org/sunset.jpg").unwrap(),
     ];
     let keys = transformrs::load_keys(".env");
     let provider = Provider::DeepInfra;
@@ -19,7 +19,7 @@ async fn main() {
         .unwrap()
         .structured()
         .unwrap();
-    println!("{}", resp.choices[0].message.content);
+    println!("{}", resp.first_text().unwrap());
 }
 
 // output:
@@ -0,0 +1,36 @@
+//! An example that runs without an API key via `mock::MockBackend`.
+
+// You can execute this example with `cargo run --example mock --features mock`
+
+use transformrs::chat::ChatBackend;
+use transformrs::chat::ChatConfig;
+use transformrs::mock::MockBackend;
+use transformrs::text_to_speech::TTSConfig;
+use transformrs::text_to_speech::TtsBackend;
+use transformrs::Message;
+
+/// Code that is written against the backend traits can use `HttpBackend` in
+/// production and `MockBackend` in tests.
+async fn say_hello<B: ChatBackend + TtsBackend>(backend: &B) {
+    let messages = vec![Message::user("Say hello.")];
+    let config = ChatConfig::default();
+    let resp = backend
+        .chat_completion(&config, "mock", &messages)
+        .await
+        .unwrap();
+    let text = resp.first_text().unwrap();
+    println!("{text}");
+
+    let config = TTSConfig::default();
+    let speech = backend.tts(&config, None, text).await.unwrap();
+    println!(
+        "Received {} bytes of {}",
+        speech.audio.len(),
+        speech.file_format
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    say_hello(&MockBackend::new()).await;
+}
@@ -7,11 +7,8 @@ use transformrs::Provider;
 #[tokio::main]
 async fn main() {
     let messages = vec![
-        Message::from_str("system", "You are a helpful assistant."),
-        Message::from_str(
-            "user",
-            "Give a one paragraph summary of the history of the internet.",
-        ),
+        Message::system("You are a helpful assistant."),
+        Message::user("Give a one paragraph summary of the history of the internet."),
     ];
     let keys = transformrs::load_keys(".env");
     let provider = Provider::DeepInfra;
@@ -6,8 +6,10 @@ use transformrs::Provider;
 async fn main() {
     let keys = transformrs::load_keys(".env");
     let key = keys.for_provider(&Provider::Hyperbolic).expect("no key");
-    let mut config = transformrs::text_to_image::TTIConfig::default();
-    config.model = "FLUX.1-dev".to_string();
+    let config = transformrs::text_to_image::TTIConfig {
+        model: "FLUX.1-dev".to_string(),
+        ..Default::default()
+    };
     let prompt = "A beautiful sunset over a calm ocean.";
     let resp = transformrs::text_to_image::text_to_image(&key, config, prompt)
         .await
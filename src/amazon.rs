@@ -0,0 +1,248 @@
+//! Amazon Polly text-to-speech with AWS Signature Version 4.
+//!
+//! AWS does not accept API keys. Instead, each request is signed with the
+//! secret access key, so the key is expected in the form
+//! "ACCESS_KEY_ID:SECRET_ACCESS_KEY" with an optional ":SESSION_TOKEN".
+
+use crate::text_to_speech::InputType;
+use crate::text_to_speech::TTSConfig;
+use crate::Error;
+use crate::Key;
+use crate::RequestParts;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+use ring::digest;
+use ring::hmac;
+use serde_json::json;
+use serde_json::Value;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Polly endpoint that is used when `base_url` is not set.
+pub(crate) const DEFAULT_DOMAIN: &str = "https://polly.us-east-1.amazonaws.com";
+
+const DEFAULT_REGION: &str = "us-east-1";
+
+const DEFAULT_VOICE: &str = "Joanna";
+
+const SERVICE: &str = "polly";
+
+struct Credentials<'a> {
+    access_key_id: &'a str,
+    secret_access_key: &'a str,
+    session_token: Option<&'a str>,
+}
+
+fn credentials(key: &Key) -> Result<Credentials<'_>, Error> {
+    let mut parts = key.key.splitn(3, ':');
+    match (parts.next(), parts.next()) {
+        (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+            access_key_id,
+            secret_access_key,
+            session_token: parts.next(),
+        }),
+        _ => Err(Error::InvalidConfig(
+            "Amazon key should be in the form ACCESS_KEY_ID:SECRET_ACCESS_KEY".to_string(),
+        )),
+    }
+}
+
+/// Host and path of the address, for example ("polly.us-east-1.amazonaws.com",
+/// "/v1/speech").
+fn host_and_path(address: &str) -> (&str, &str) {
+    let without_scheme = address.split_once("://").map_or(address, |(_, rest)| rest);
+    match without_scheme.find('/') {
+        Some(i) => (&without_scheme[..i], &without_scheme[i..]),
+        None => (without_scheme, "/"),
+    }
+}
+
+/// Region from a host such as "polly.eu-west-1.amazonaws.com".
+fn region(host: &str) -> &str {
+    match host.strip_prefix("polly.") {
+        Some(rest) if rest.ends_with(".amazonaws.com") => rest.split('.').next().unwrap(),
+        _ => DEFAULT_REGION,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data.as_bytes()).as_ref().to_vec()
+}
+
+/// Timestamp in the ISO 8601 basic format, for example "20150830T123600Z".
+fn amz_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Signature of the canonical request.
+///
+/// See <https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv-create-signed-request.html>.
+fn signature(
+    secret_access_key: &str,
+    amz_date: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        sha256_hex(canonical_request.as_bytes())
+    );
+    let key = format!("AWS4{secret_access_key}");
+    let key = hmac_sha256(key.as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    let key = hmac_sha256(&key, "aws4_request");
+    hex(&hmac_sha256(&key, &string_to_sign))
+}
+
+/// Headers, including `Authorization`, for a signed POST request.
+fn signed_headers(
+    key: &Key,
+    address: &str,
+    body: &[u8],
+    time: SystemTime,
+) -> Result<HeaderMap, Error> {
+    let credentials = credentials(key)?;
+    let (host, path) = host_and_path(address);
+    let region = region(host);
+    let amz_date = amz_date(time);
+    let content_type = "application/json";
+    // Sorted by name, as required for the canonical request.
+    let mut signed = vec![
+        ("content-type", content_type),
+        ("host", host),
+        ("x-amz-date", amz_date.as_str()),
+    ];
+    if let Some(session_token) = credentials.session_token {
+        signed.push(("x-amz-security-token", session_token));
+    }
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_names = signed
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "POST\n{path}\n\n{canonical_headers}\n{signed_names}\n{}",
+        sha256_hex(body)
+    );
+    let signature = signature(
+        credentials.secret_access_key,
+        &amz_date,
+        region,
+        SERVICE,
+        &canonical_request,
+    );
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}/{region}/{SERVICE}/aws4_request, SignedHeaders={signed_names}, Signature={signature}",
+        credentials.access_key_id,
+        &amz_date[..8],
+    );
+    let mut headers = HeaderMap::new();
+    for (name, value) in signed {
+        // The host header is set by the client from the address.
+        if name != "host" {
+            headers.insert(name, HeaderValue::from_str(value)?);
+        }
+    }
+    headers.insert("Authorization", HeaderValue::from_str(&authorization)?);
+    Ok(headers)
+}
+
+/// Polly `OutputFormat` and the resulting file format.
+pub(crate) fn output_format(
+    output_format: Option<&str>,
+) -> Result<(&'static str, &'static str), Error> {
+    match output_format {
+        None | Some("mp3") => Ok(("mp3", "mp3")),
+        Some("ogg") | Some("ogg_vorbis") => Ok(("ogg_vorbis", "ogg")),
+        Some("pcm") => Ok(("pcm", "pcm")),
+        Some(output_format) => Err(Error::InvalidConfig(format!(
+            "output format {output_format} is not supported by Amazon"
+        ))),
+    }
+}
+
+/// `SynthesizeSpeech` request that is signed for `time`.
+///
+/// The model is passed as the engine, for example "neural" or "generative".
+pub(crate) fn request_parts(
+    address: String,
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+    time: SystemTime,
+) -> Result<RequestParts, Error> {
+    if config.speed.is_some() {
+        return Err(Error::InvalidConfig(
+            "speed is not supported by Amazon; use SSML prosody instead".to_string(),
+        ));
+    }
+    let (format, _) = output_format(config.output_format.as_deref())?;
+    let text_type = match config.input_type {
+        InputType::Text => "text",
+        InputType::Ssml => "ssml",
+    };
+    let mut body = json!({
+        "Text": text,
+        "TextType": text_type,
+        "OutputFormat": format,
+        "VoiceId": config.voice.as_deref().unwrap_or(DEFAULT_VOICE),
+    });
+    if let Some(model) = model {
+        body["Engine"] = Value::from(model);
+    }
+    if let Some(language_code) = &config.language_code {
+        body["LanguageCode"] = Value::from(language_code.clone());
+    }
+    if let Some(other) = &config.other {
+        for (key, value) in other {
+            body[key] = value.clone();
+        }
+    }
+    // The body is serialized the same way when sending the request, so the
+    // signature matches.
+    let serialized = serde_json::to_vec(&body)?;
+    let headers = signed_headers(key, &address, &serialized, time)?;
+    Ok(RequestParts {
+        address,
+        headers,
+        body,
+    })
+}
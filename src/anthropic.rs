@@ -0,0 +1,336 @@
+//! Translation between the OpenAI chat format and the Anthropic Messages API.
+//!
+//! Anthropic's API differs from the OpenAI API in that, for example, the
+//! system prompt is a top-level field and `max_tokens` is required. To allow
+//! `chat_completion` to work transparently, requests are translated into the
+//! Anthropic format and responses back into the OpenAI format.
+
+use crate::chat::ChatCompletionChunk;
+use crate::chat::ChatConfig;
+use crate::chat::ChunkChoice;
+use crate::chat::Delta;
+use crate::chat::FunctionDelta;
+use crate::chat::ToolCallDelta;
+use crate::chat::Usage;
+use crate::Content;
+use crate::Message;
+use crate::Role;
+use crate::SubContent;
+use serde_json::json;
+use serde_json::Value;
+
+/// Anthropic requires `max_tokens`, so this is used when it is not set.
+pub(crate) const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+fn text(content: &Content) -> String {
+    match content {
+        Content::Text(text) => text.clone(),
+        Content::Collection(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                SubContent::TextContent { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+fn image_block(image_url: &str) -> Value {
+    // For example, "data:image/png;base64,iVBORw0KGgo...".
+    let data_url = image_url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .filter(|(media_type, _)| media_type.starts_with("image/"));
+    match data_url {
+        Some((media_type, data)) => json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": media_type, "data": data}
+        }),
+        None => json!({
+            "type": "image",
+            "source": {"type": "url", "url": image_url}
+        }),
+    }
+}
+
+fn content_blocks(content: &Content) -> Vec<Value> {
+    match content {
+        Content::Text(text) if text.is_empty() => vec![],
+        Content::Text(text) => vec![json!({"type": "text", "text": text})],
+        Content::Collection(items) => items
+            .iter()
+            .map(|item| match item {
+                SubContent::TextContent { text } => json!({"type": "text", "text": text}),
+                SubContent::ImageUrlContent { image_url } => image_block(image_url),
+            })
+            .collect(),
+    }
+}
+
+fn message(message: &Message) -> Value {
+    if message.role == Role::Tool {
+        return json!({
+            "role": "user",
+            "content": [{
+                "type": "tool_result",
+                "tool_use_id": message.tool_call_id,
+                "content": text(&message.content),
+            }]
+        });
+    }
+    let mut blocks = content_blocks(&message.content);
+    if let Some(tool_calls) = &message.tool_calls {
+        for tool_call in tool_calls {
+            blocks.push(json!({
+                "type": "tool_use",
+                "id": tool_call.id,
+                "name": tool_call.name,
+                "input": tool_call.arguments,
+            }));
+        }
+    }
+    // The prefix up to and including the marked block is cached.
+    if message.cache_control {
+        if let Some(last) = blocks.last_mut() {
+            last["cache_control"] = json!({"type": "ephemeral"});
+        }
+    }
+    json!({
+        "role": message.role,
+        "content": blocks,
+    })
+}
+
+/// Request body for the Anthropic Messages API.
+pub(crate) fn request_body(
+    config: &ChatConfig,
+    model: &str,
+    stream: bool,
+    messages: &[Message],
+) -> Value {
+    let system = messages
+        .iter()
+        .filter(|m| m.role == Role::System)
+        .map(|m| text(&m.content))
+        .collect::<Vec<_>>();
+    let cache_system = messages
+        .iter()
+        .any(|m| m.role == Role::System && m.cache_control);
+    let messages = messages
+        .iter()
+        .filter(|m| m.role != Role::System)
+        .map(message)
+        .collect::<Vec<_>>();
+    let mut body = json!({
+        "model": model,
+        "messages": messages,
+        "max_tokens": config.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": stream,
+    });
+    if !system.is_empty() && cache_system {
+        // Only blocks can be marked for caching, so the string form is not used.
+        body["system"] = json!([{
+            "type": "text",
+            "text": system.join("\n"),
+            "cache_control": {"type": "ephemeral"},
+        }]);
+    } else if !system.is_empty() {
+        body["system"] = Value::from(system.join("\n"));
+    }
+    if let Some(temperature) = config.temperature {
+        body["temperature"] = Value::from(temperature);
+    }
+    if let Some(top_p) = config.top_p {
+        body["top_p"] = Value::from(top_p);
+    }
+    if let Some(user) = &config.user {
+        body["metadata"] = json!({"user_id": user});
+    }
+    if let Some(stop) = &config.stop {
+        body["stop_sequences"] = Value::from(stop.clone());
+    }
+    if let Some(tools) = &config.tools {
+        let tools = tools
+            .iter()
+            .map(|tool| {
+                let mut tool_json = json!({
+                    "name": tool.name,
+                    "input_schema": tool.parameters,
+                });
+                // Anthropic rejects a null description.
+                if let Some(description) = &tool.description {
+                    tool_json["description"] = Value::from(description.clone());
+                }
+                tool_json
+            })
+            .collect::<Vec<_>>();
+        body["tools"] = Value::from(tools);
+    }
+    body
+}
+
+fn finish_reason(stop_reason: &Value) -> Value {
+    match stop_reason.as_str() {
+        Some("end_turn" | "stop_sequence") => Value::from("stop"),
+        Some("max_tokens") => Value::from("length"),
+        Some("tool_use") => Value::from("tool_calls"),
+        Some(reason) => Value::from(reason),
+        None => Value::Null,
+    }
+}
+
+/// Translate an Anthropic response into an OpenAI chat completion.
+///
+/// Errors are returned as is since they already contain `error.message`.
+pub(crate) fn response(resp: &Value) -> Value {
+    if resp.get("error").is_some() {
+        return resp.clone();
+    }
+    let empty = vec![];
+    let blocks = resp["content"].as_array().unwrap_or(&empty);
+    let text = blocks
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect::<String>();
+    let tool_calls = blocks
+        .iter()
+        .filter(|block| block["type"] == "tool_use")
+        .map(|block| {
+            json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string(),
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut message = json!({
+        "role": "assistant",
+        "content": text,
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::from(tool_calls);
+    }
+    let input_tokens = resp["usage"]["input_tokens"].as_u64().unwrap_or(0);
+    let output_tokens = resp["usage"]["output_tokens"].as_u64().unwrap_or(0);
+    json!({
+        "id": resp["id"],
+        "object": "chat.completion",
+        "created": 0,
+        "model": resp["model"],
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(&resp["stop_reason"]),
+        }],
+        "usage": {
+            "prompt_tokens": input_tokens,
+            "completion_tokens": output_tokens,
+            "total_tokens": input_tokens + output_tokens,
+            "cache_creation_input_tokens": resp["usage"]["cache_creation_input_tokens"],
+            "cache_read_input_tokens": resp["usage"]["cache_read_input_tokens"],
+        }
+    })
+}
+
+fn chunk(
+    content: Option<String>,
+    tool_call: Option<ToolCallDelta>,
+    finish_reason: Option<String>,
+) -> ChatCompletionChunk {
+    ChatCompletionChunk {
+        id: None,
+        object: "chat.completion.chunk".to_string(),
+        created: 0,
+        model: String::new(),
+        system_fingerprint: None,
+        choices: vec![ChunkChoice {
+            index: 0,
+            delta: Delta {
+                role: None,
+                content,
+                reasoning: None,
+                tool_calls: tool_call.map(|tool_call| vec![tool_call]),
+            },
+            finish_reason,
+        }],
+    }
+}
+
+/// Translate an Anthropic streaming event into an OpenAI chunk.
+///
+/// Only `message_start`, which contains the id and model, and events that
+/// contain text, tool calls, or the stop reason are translated. The index of
+/// the content block is used as the index of the tool call.
+pub(crate) fn stream_event(json: &Value) -> Option<ChatCompletionChunk> {
+    let tool_call = |id: Option<&str>, name: Option<&str>, arguments: Option<&str>| {
+        Some(ToolCallDelta {
+            index: json["index"].as_u64()?,
+            id: id.map(|id| id.to_string()),
+            function: Some(FunctionDelta {
+                name: name.map(|name| name.to_string()),
+                arguments: arguments.map(|arguments| arguments.to_string()),
+            }),
+        })
+    };
+    match json["type"].as_str()? {
+        "message_start" => {
+            let message = &json["message"];
+            let mut chunk = chunk(None, None, None);
+            chunk.id = message["id"].as_str().map(|id| id.to_string());
+            chunk.model = message["model"].as_str().unwrap_or_default().to_string();
+            chunk.choices[0].delta.role = Some("assistant".to_string());
+            Some(chunk)
+        }
+        "content_block_start" if json["content_block"]["type"] == "tool_use" => {
+            let block = &json["content_block"];
+            let tool_call = tool_call(block["id"].as_str(), block["name"].as_str(), None)?;
+            Some(chunk(None, Some(tool_call), None))
+        }
+        "content_block_delta" if json["delta"]["type"] == "input_json_delta" => {
+            let arguments = json["delta"]["partial_json"].as_str();
+            Some(chunk(None, Some(tool_call(None, None, arguments)?), None))
+        }
+        "content_block_delta" => {
+            let text = json["delta"]["text"].as_str()?;
+            Some(chunk(Some(text.to_string()), None, None))
+        }
+        "message_delta" => {
+            let reason = finish_reason(&json["delta"]["stop_reason"]);
+            Some(chunk(None, None, reason.as_str().map(|r| r.to_string())))
+        }
+        _ => None,
+    }
+}
+
+/// Update the usage from an Anthropic streaming event.
+///
+/// The input tokens are in `message_start` and the output tokens in
+/// `message_delta`.
+pub(crate) fn stream_usage(json: &Value, usage: &mut Option<Usage>) {
+    let (input, output) = match json["type"].as_str() {
+        Some("message_start") => (Some(&json["message"]["usage"]), None),
+        Some("message_delta") => (None, json["usage"]["output_tokens"].as_u64()),
+        _ => return,
+    };
+    let current = usage.get_or_insert(Usage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        total_tokens: 0,
+        cache_creation_input_tokens: None,
+        cache_read_input_tokens: None,
+    });
+    if let Some(input) = input {
+        current.prompt_tokens = input["input_tokens"].as_u64().unwrap_or(0);
+        current.cache_creation_input_tokens = input["cache_creation_input_tokens"].as_u64();
+        current.cache_read_input_tokens = input["cache_read_input_tokens"].as_u64();
+    }
+    if let Some(output) = output {
+        current.completion_tokens = output;
+    }
+    current.total_tokens = current.prompt_tokens + current.completion_tokens;
+}
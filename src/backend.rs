@@ -0,0 +1,53 @@
+//! Backend that sends the requests over HTTP.
+
+use crate::chat::chat_completion_with_config;
+use crate::chat::ChatBackend;
+use crate::chat::ChatCompletion;
+use crate::chat::ChatConfig;
+use crate::text_to_speech::tts;
+use crate::text_to_speech::Speech;
+use crate::text_to_speech::TTSConfig;
+use crate::text_to_speech::TtsBackend;
+use crate::Error;
+use crate::Key;
+use crate::Message;
+
+/// Backend that sends the requests over HTTP to the provider of the key.
+///
+/// Implements `chat::ChatBackend` and `text_to_speech::TtsBackend`, so that
+/// application code can be written against the traits and tested with a mock.
+#[derive(Clone, Debug)]
+pub struct HttpBackend {
+    pub key: Key,
+}
+
+impl HttpBackend {
+    pub fn new(key: Key) -> Self {
+        Self { key }
+    }
+}
+
+impl ChatBackend for HttpBackend {
+    async fn chat_completion(
+        &self,
+        config: &ChatConfig,
+        model: &str,
+        messages: &[Message],
+    ) -> Result<ChatCompletion, Error> {
+        let provider = &self.key.provider;
+        chat_completion_with_config(provider, &self.key, config, model, messages)
+            .await?
+            .structured()
+    }
+}
+
+impl TtsBackend for HttpBackend {
+    async fn tts(
+        &self,
+        config: &TTSConfig,
+        model: Option<&str>,
+        text: &str,
+    ) -> Result<Speech, Error> {
+        tts(&self.key, config, model, text).await?.structured()
+    }
+}
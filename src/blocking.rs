@@ -0,0 +1,159 @@
+//! Blocking API.
+//!
+//! Functions that block the current thread until the response is received, so
+//! that no async runtime is needed. The requests are the same as the ones sent
+//! by the async functions.
+//!
+//! Requires the `blocking` feature. Like `reqwest::blocking`, these functions
+//! must not be called from within an async runtime.
+
+use crate::chat::ChatCompletionResponse;
+use crate::chat::ChatConfig;
+use crate::text_to_speech::SpeechResponse;
+use crate::text_to_speech::TTSConfig;
+use crate::Error;
+use crate::Key;
+use crate::Message;
+use crate::Provider;
+use crate::RateLimitInfo;
+use crate::RequestParts;
+use crate::RetryConfig;
+use bytes::Bytes;
+use reqwest::blocking::Client;
+use reqwest::blocking::Response;
+use std::io::Read;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Blocking version of `crate::client_builder`.
+#[allow(clippy::let_and_return)]
+fn client_builder() -> reqwest::blocking::ClientBuilder {
+    let builder = Client::builder();
+    #[cfg(feature = "rustls-tls")]
+    let builder = builder.use_rustls_tls();
+    #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+    let builder = builder.use_native_tls();
+    builder
+}
+
+/// Blocking version of `crate::send`.
+fn send(
+    parts: RequestParts,
+    retry: Option<&RetryConfig>,
+    timeout: Option<Duration>,
+) -> Result<Response, Error> {
+    let start = Instant::now();
+    let result = send_with_retry(parts, retry, timeout);
+    crate::trace_completion(start, result.as_ref().map(|resp| resp.status().as_u16()));
+    result
+}
+
+fn send_with_retry(
+    parts: RequestParts,
+    retry: Option<&RetryConfig>,
+    timeout: Option<Duration>,
+) -> Result<Response, Error> {
+    let client = client_builder().build()?;
+    let mut request = client
+        .post(parts.address)
+        .headers(parts.headers)
+        .json(&parts.body);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let retry = match retry {
+        Some(retry) => retry,
+        None => return Ok(request.send()?),
+    };
+    let mut attempt = 0;
+    loop {
+        // The body is JSON, so the request can always be cloned.
+        let current = request.try_clone().unwrap();
+        let result = current.send();
+        let delay = match &result {
+            Ok(resp) if crate::is_retryable_status(resp.status()) => {
+                retry.delay(attempt, Some(resp.headers()))
+            }
+            Err(e) if e.is_timeout() || e.is_connect() => retry.delay(attempt, None),
+            _ => return Ok(result?),
+        };
+        if retry.max_retries <= attempt {
+            return Ok(result?);
+        }
+        tracing::debug!("Retrying request in {delay:?} (attempt {})", attempt + 1);
+        std::thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Blocking version of `chat::chat_completion`.
+pub fn chat_completion(
+    provider: &Provider,
+    key: &Key,
+    model: &str,
+    messages: &[Message],
+) -> Result<ChatCompletionResponse, Error> {
+    let config = ChatConfig::default();
+    chat_completion_with_config(provider, key, &config, model, messages)
+}
+
+/// Blocking version of `chat::chat_completion_with_config`.
+///
+/// `config.client` is ignored since it is an async client.
+pub fn chat_completion_with_config(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    messages: &[Message],
+) -> Result<ChatCompletionResponse, Error> {
+    let parts = crate::chat::request_parts(provider, key, config, model, false, messages)?;
+    tracing::debug!("Requesting chat: {}", parts.body);
+    let _span = crate::request_span("chat", provider, Some(model)).entered();
+    let resp = send(parts, config.retry.as_ref(), config.timeout)?;
+    Ok(ChatCompletionResponse {
+        provider: provider.clone(),
+        status: resp.status().into(),
+        request_id: crate::request_id(resp.headers()),
+        rate_limit: RateLimitInfo::from_headers(resp.headers()),
+        resp: resp.bytes()?,
+    })
+}
+
+/// Blocking version of `text_to_speech::tts`.
+///
+/// `config.client` is ignored since it is an async client.
+pub fn tts(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<SpeechResponse, Error> {
+    let parts = crate::text_to_speech::request_parts(key, config, model, text)?;
+    tracing::debug!("Requesting text-to-speech: {}", parts.body);
+    let _span = crate::request_span("tts", &key.provider, model).entered();
+    let resp = send(parts, config.retry.as_ref(), config.timeout)?;
+    Ok(SpeechResponse {
+        provider: key.provider.clone(),
+        status: resp.status().into(),
+        output_format: config.output_format.clone(),
+        rate_limit: RateLimitInfo::from_headers(resp.headers()),
+        content_type: crate::content_type(resp.headers()),
+        resp: read_audio(resp)?,
+    })
+}
+
+/// Blocking version of `text_to_speech::read_audio`.
+///
+/// Returns `Error::PartialAudio` with the bytes that were received when the
+/// connection fails before the body is complete.
+fn read_audio(mut resp: Response) -> Result<Bytes, Error> {
+    let mut received = vec![];
+    match resp.read_to_end(&mut received) {
+        Ok(_) => Ok(Bytes::from(received)),
+        Err(e) => Err(Error::PartialAudio {
+            bytes: Bytes::from(received),
+            source: Box::new(Error::from(e)),
+        }),
+    }
+}
@@ -1,80 +1,651 @@
+use crate::models::ModelAlias;
 use crate::request_headers;
+use crate::Content;
+use crate::Error;
 use crate::Key;
+use crate::Keys;
 use crate::Message;
 use crate::Provider;
+use crate::RateLimitInfo;
+use crate::RequestParts;
+use crate::RetryConfig;
+use crate::Role;
+use crate::SubContent;
 use async_stream::stream;
 use bytes::Bytes;
 use futures::Stream;
 use futures::StreamExt;
 use reqwest;
+use reqwest::header::HeaderMap;
 use reqwest::Response;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
-use std::error::Error;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
+use tracing::Instrument;
 
-fn address(provider: &Provider) -> String {
-    let base_url = crate::openai_base_url(provider);
-    format!("{}/chat/completions", base_url)
-}
-
-async fn request_chat_completion(
+fn address(
     provider: &Provider,
     key: &Key,
+    base_url: Option<&str>,
+    model: &str,
+) -> Result<String, Error> {
+    if provider == &Provider::Azure {
+        return crate::azure_address(key, base_url, model, "chat/completions");
+    }
+    let base_url = crate::openai_base_url(provider, base_url);
+    if provider == &Provider::Anthropic {
+        return Ok(format!("{}/messages", base_url));
+    }
+    Ok(format!("{}/chat/completions", base_url))
+}
+
+/// Chat completion config.
+///
+/// Fields that are `None` are not sent to the provider, so the provider's
+/// defaults are used.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChatConfig {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop: Option<Vec<String>>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    /// Number of completions to generate, which are returned as separate choices.
+    ///
+    /// Not supported by Anthropic, for which `Error::InvalidConfig` is returned.
+    pub n: Option<u32>,
+    /// Seed for sampling, so that repeated requests return the same output.
+    ///
+    /// Determinism is best effort; compare `system_fingerprint` in the response
+    /// to detect backend changes. Not supported by Anthropic, for which
+    /// `Error::InvalidConfig` is returned.
+    pub seed: Option<u64>,
+    /// Return the log probabilities of the output tokens.
+    ///
+    /// Not supported by Anthropic, for which `Error::InvalidConfig` is returned.
+    pub logprobs: Option<bool>,
+    /// Number of most likely alternatives to return per token (0 to 20).
+    ///
+    /// Requires `logprobs` to be `true`.
+    pub top_logprobs: Option<u8>,
+    /// Bias from -100 to 100 that is added to the logits of the token IDs,
+    /// for example to force a "yes" or "no" answer.
+    ///
+    /// The token IDs depend on the tokenizer of the model. Not supported by
+    /// Anthropic, for which `Error::InvalidConfig` is returned.
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    /// Functions that the model can call.
+    pub tools: Option<Vec<Tool>>,
+    /// Force the model to respond with JSON.
+    ///
+    /// Not supported by Anthropic, for which `Error::InvalidConfig` is returned.
+    pub response_format: Option<ResponseFormat>,
+    /// How Groq returns the reasoning of reasoning models, one of "parsed",
+    /// "raw", or "hidden".
+    ///
+    /// With "parsed", the reasoning is returned in `Message::reasoning` instead
+    /// of inside `<think>` tags in the content. Only supported by Groq and
+    /// `Provider::Other`, for others `Error::InvalidConfig` is returned.
+    pub reasoning_format: Option<String>,
+    /// Output types, for example `["text", "audio"]` to also receive the
+    /// answer as audio in `Message::audio`.
+    ///
+    /// Only supported by OpenAI, Azure, and `Provider::Other`, for others
+    /// `Error::InvalidConfig` is returned.
+    pub modalities: Option<Vec<String>>,
+    /// Voice and format of the audio when `modalities` contains "audio".
+    pub audio: Option<AudioConfig>,
+    /// See [common options](crate#common-options).
+    ///
+    /// Sent as `metadata.user_id` to Anthropic.
+    pub user: Option<String>,
+    pub other: Option<HashMap<String, Value>>,
+    /// See [common options](crate#common-options).
+    pub retry: Option<RetryConfig>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
+    /// Translate canonical model names, such as "llama-3.3-70b", to the
+    /// identifier of the provider before sending the request.
+    #[serde(skip)]
+    pub model_alias: Option<ModelAlias>,
+}
+
+/// Audio output of a chat completion, see `ChatConfig.modalities`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AudioConfig {
+    /// For example, "alloy".
+    pub voice: String,
+    /// For example, "wav" or "mp3".
+    pub format: String,
+}
+
+impl ChatConfig {
+    /// Builder for the config, for example
+    /// `ChatConfig::builder().temperature(0.0).max_tokens(100).build()`.
+    pub fn builder() -> ChatConfigBuilder {
+        ChatConfigBuilder::default()
+    }
+}
+
+/// Builder for `ChatConfig`.
+///
+/// Fields that are not set keep their default.
+#[derive(Clone, Debug, Default)]
+pub struct ChatConfigBuilder {
+    config: ChatConfig,
+}
+
+impl ChatConfigBuilder {
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.config.temperature = Some(temperature);
+        self
+    }
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.config.top_p = Some(top_p);
+        self
+    }
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.config.max_tokens = Some(max_tokens);
+        self
+    }
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.config.stop = Some(stop);
+        self
+    }
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.config.frequency_penalty = Some(frequency_penalty);
+        self
+    }
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.config.presence_penalty = Some(presence_penalty);
+        self
+    }
+    pub fn n(mut self, n: u32) -> Self {
+        self.config.n = Some(n);
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+    pub fn logprobs(mut self, logprobs: bool) -> Self {
+        self.config.logprobs = Some(logprobs);
+        self
+    }
+    pub fn top_logprobs(mut self, top_logprobs: u8) -> Self {
+        self.config.top_logprobs = Some(top_logprobs);
+        self
+    }
+    /// Add a bias for the token ID, see `ChatConfig.logit_bias`.
+    pub fn logit_bias(mut self, token_id: u32, bias: f32) -> Self {
+        self.config
+            .logit_bias
+            .get_or_insert_with(HashMap::new)
+            .insert(token_id, bias);
+        self
+    }
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.config.tools = Some(tools);
+        self
+    }
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.config.response_format = Some(response_format);
+        self
+    }
+    pub fn reasoning_format(mut self, reasoning_format: &str) -> Self {
+        self.config.reasoning_format = Some(reasoning_format.to_string());
+        self
+    }
+    pub fn modalities(mut self, modalities: &[&str]) -> Self {
+        self.config.modalities = Some(modalities.iter().map(|m| m.to_string()).collect());
+        self
+    }
+    pub fn audio(mut self, voice: &str, format: &str) -> Self {
+        self.config.audio = Some(AudioConfig {
+            voice: voice.to_string(),
+            format: format.to_string(),
+        });
+        self
+    }
+    pub fn user(mut self, user: &str) -> Self {
+        self.config.user = Some(user.to_string());
+        self
+    }
+    /// Add a provider-specific field to the request body.
+    pub fn other(mut self, key: &str, value: Value) -> Self {
+        self.config
+            .other
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value);
+        self
+    }
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry = Some(retry);
+        self
+    }
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.config.client = Some(client);
+        self
+    }
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.config.base_url = Some(base_url.to_string());
+        self
+    }
+    /// Add a header to the request.
+    pub fn extra_header(mut self, name: &str, value: &str) -> Self {
+        self.config
+            .extra_headers
+            .get_or_insert_with(HashMap::new)
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+    pub fn model_alias(mut self, model_alias: ModelAlias) -> Self {
+        self.config.model_alias = Some(model_alias);
+        self
+    }
+    pub fn build(self) -> ChatConfig {
+        self.config
+    }
+}
+
+/// A function that the model can call.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// JSON schema of the arguments.
+    pub parameters: Value,
+}
+
+/// Format of the model's response.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// Respond with a valid JSON object.
+    JsonObject,
+    /// Respond with JSON that matches the schema.
+    JsonSchema {
+        json_schema: JsonSchema,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonSchema {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    /// Whether the response must strictly follow the schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
+}
+
+fn request_body(
+    config: &ChatConfig,
     model: &str,
     stream: bool,
     messages: &[Message],
-) -> Result<Response, Box<dyn Error + Send + Sync>> {
-    let address = address(provider);
-    let body = serde_json::json!({
+) -> Result<Value, Error> {
+    let mut body = serde_json::json!({
         "model": model,
         "messages": messages,
         "stream": stream,
     });
-    let client = if provider == &Provider::Google {
-        // Without this, the request will fail with 400 INVALID_ARGUMENT.
-        // According to the docs, a 400 error is returned when the request body
-        // is malformed.  Why rustls tls fixes this, I do not know.
-        reqwest::Client::builder().use_rustls_tls().build()?
+    if let Some(temperature) = config.temperature {
+        body["temperature"] = Value::from(temperature);
+    }
+    if let Some(top_p) = config.top_p {
+        body["top_p"] = Value::from(top_p);
+    }
+    if let Some(max_tokens) = config.max_tokens {
+        body["max_tokens"] = Value::from(max_tokens);
+    }
+    if let Some(stop) = &config.stop {
+        body["stop"] = Value::from(stop.clone());
+    }
+    if let Some(frequency_penalty) = config.frequency_penalty {
+        body["frequency_penalty"] = Value::from(frequency_penalty);
+    }
+    if let Some(presence_penalty) = config.presence_penalty {
+        body["presence_penalty"] = Value::from(presence_penalty);
+    }
+    if let Some(n) = config.n {
+        body["n"] = Value::from(n);
+    }
+    if let Some(seed) = config.seed {
+        body["seed"] = Value::from(seed);
+    }
+    if let Some(logprobs) = config.logprobs {
+        body["logprobs"] = Value::from(logprobs);
+    }
+    if let Some(top_logprobs) = config.top_logprobs {
+        body["top_logprobs"] = Value::from(top_logprobs);
+    }
+    if let Some(logit_bias) = &config.logit_bias {
+        // JSON keys are strings, so the token IDs are sent as, for example, "1234".
+        body["logit_bias"] = logit_bias
+            .iter()
+            .map(|(token_id, bias)| (token_id.to_string(), Value::from(*bias)))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+    }
+    if let Some(tools) = &config.tools {
+        let tools = tools
+            .iter()
+            .map(|tool| serde_json::json!({"type": "function", "function": tool}))
+            .collect::<Vec<_>>();
+        body["tools"] = Value::from(tools);
+    }
+    if let Some(user) = &config.user {
+        body["user"] = Value::from(user.clone());
+    }
+    if let Some(response_format) = &config.response_format {
+        body["response_format"] = serde_json::to_value(response_format)?;
+    }
+    if let Some(reasoning_format) = &config.reasoning_format {
+        body["reasoning_format"] = Value::from(reasoning_format.clone());
+    }
+    if let Some(modalities) = &config.modalities {
+        body["modalities"] = Value::from(modalities.clone());
+    }
+    if let Some(audio) = &config.audio {
+        body["audio"] = serde_json::json!({
+            "voice": audio.voice,
+            "format": audio.format,
+        });
+    }
+    Ok(body)
+}
+
+pub(crate) fn request_parts(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    stream: bool,
+    messages: &[Message],
+) -> Result<RequestParts, Error> {
+    let model = match &config.model_alias {
+        Some(model_alias) => model_alias.resolve(provider, model),
+        None => model,
+    };
+    let address = address(provider, key, config.base_url.as_deref(), model)?;
+    if config.response_format.is_some() && provider == &Provider::Anthropic {
+        return Err(Error::InvalidConfig(format!(
+            "response_format is not supported by {provider}"
+        )));
+    }
+    if config.n.is_some_and(|n| n > 1) && provider == &Provider::Anthropic {
+        return Err(Error::InvalidConfig(format!(
+            "n > 1 is not supported by {provider}"
+        )));
+    }
+    if config.seed.is_some() && provider == &Provider::Anthropic {
+        return Err(Error::InvalidConfig(format!(
+            "seed is not supported by {provider}"
+        )));
+    }
+    if config.logprobs == Some(true) && provider == &Provider::Anthropic {
+        return Err(Error::InvalidConfig(format!(
+            "logprobs is not supported by {provider}"
+        )));
+    }
+    if config.logit_bias.is_some() && provider == &Provider::Anthropic {
+        return Err(Error::InvalidConfig(format!(
+            "logit_bias is not supported by {provider}"
+        )));
+    }
+    if config.reasoning_format.is_some() && !matches!(provider, Provider::Groq | Provider::Other(_))
+    {
+        return Err(Error::InvalidConfig(format!(
+            "reasoning_format is not supported by {provider}"
+        )));
+    }
+    let supports_audio = matches!(
+        provider,
+        Provider::Azure | Provider::OpenAI | Provider::Other(_)
+    );
+    if config.modalities.is_some() && !supports_audio {
+        return Err(Error::InvalidConfig(format!(
+            "modalities is not supported by {provider}"
+        )));
+    }
+    if config.audio.is_some() && !supports_audio {
+        return Err(Error::InvalidConfig(format!(
+            "audio is not supported by {provider}"
+        )));
+    }
+    let mut body = if provider == &Provider::Anthropic {
+        crate::anthropic::request_body(config, model, stream, messages)
     } else {
-        reqwest::Client::new()
+        request_body(config, model, stream, messages)?
     };
-    tracing::debug!("Requesting chat: {body}");
-    let resp = client
-        .post(address)
-        .headers(request_headers(key)?)
-        .json(&body)
-        .send()
+    if let Some(other) = &config.other {
+        for (key, value) in other {
+            body[key] = value.clone();
+        }
+    }
+    let mut headers = request_headers(key)?;
+    crate::insert_idempotency_key(&mut headers, provider, config.retry.as_ref())?;
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    Ok(RequestParts {
+        address,
+        headers,
+        body,
+    })
+}
+
+/// Build the request that `chat_completion_with_config` would send, without
+/// sending it.
+///
+/// Useful for debugging and for testing the body without network access.
+pub fn build_chat_request(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    messages: &[Message],
+) -> Result<(Url, HeaderMap, Value), Error> {
+    request_parts(provider, key, config, model, false, messages)?.into_tuple()
+}
+
+async fn request_chat_completion(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    stream: bool,
+    messages: &[Message],
+) -> Result<Response, Error> {
+    let parts = request_parts(provider, key, config, model, stream, messages)?;
+    let client = crate::client(&config.client)?;
+    tracing::debug!("Requesting chat: {}", parts.body);
+    let request = client
+        .post(parts.address)
+        .headers(parts.headers)
+        .json(&parts.body);
+    let span = crate::request_span("chat", provider, Some(model));
+    let resp = crate::send(request, config.retry.as_ref(), config.timeout)
+        .instrument(span)
         .await?;
     Ok(resp)
 }
 
+/// Why the model stopped generating.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FinishReason {
+    /// The model finished or a stop sequence was reached.
+    Stop,
+    /// The maximum number of tokens was reached, so the output is truncated.
+    Length,
+    /// The model called one or more tools.
+    ToolCalls,
+    /// The output was omitted by a content filter.
+    ContentFilter,
+    Other(String),
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            reason => FinishReason::Other(reason.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Log probability of a token in the output.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub bytes: Option<Vec<u8>>,
+    /// Most likely alternatives at this position, see `ChatConfig.top_logprobs`.
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Logprobs {
+    pub content: Option<Vec<TokenLogprob>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Choice {
     pub index: u64,
     pub message: Message,
-    pub logprobs: Option<String>,
+    /// Only set when `ChatConfig.logprobs` is `true`.
+    pub logprobs: Option<Logprobs>,
+    /// For example, "stop", "length", or "tool_calls". See also `reason`.
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Choice {
+    /// Typed version of `finish_reason`.
+    ///
+    /// `Some(FinishReason::Length)` means that the output was truncated due to
+    /// `max_tokens`.
+    pub fn reason(&self) -> Option<FinishReason> {
+        self.finish_reason.as_deref().map(FinishReason::from)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: u64,
     pub completion_tokens: u64,
     pub total_tokens: u64,
+    /// Tokens that were written to the prompt cache.
+    ///
+    /// Only reported by Anthropic.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_creation_input_tokens: Option<u64>,
+    /// Tokens that were read from the prompt cache.
+    ///
+    /// Reported by Anthropic and, as `prompt_cache_hit_tokens`, by DeepSeek.
+    #[serde(
+        default,
+        alias = "prompt_cache_hit_tokens",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cache_read_input_tokens: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatCompletion {
+    /// ID of the completion in the body, for example "chatcmpl-123".
     pub id: Option<String>,
+    /// ID of the request from the `x-request-id` header, which is useful to
+    /// quote when contacting the provider's support.
+    #[serde(default)]
+    pub request_id: Option<String>,
     pub object: String,
     pub created: u64,
+    /// Model that served the request, as reported by the provider.
+    ///
+    /// This can differ from the requested model, for example when the
+    /// provider resolves an alias.
+    #[serde(default)]
     pub model: String,
+    /// Backend configuration that generated the response.
+    ///
+    /// Together with `ChatConfig.seed`, this can be used to detect when the
+    /// output may change because the provider changed the backend.
     pub system_fingerprint: Option<String>,
     pub choices: Vec<Choice>,
     pub service_tier: Option<String>,
-    pub usage: Usage,
+    /// Token usage, which is `None` when the provider does not report it.
+    pub usage: Option<Usage>,
+}
+
+impl ChatCompletion {
+    /// Model that served the request, or `None` when the provider did not
+    /// report it.
+    pub fn model(&self) -> Option<&str> {
+        if self.model.is_empty() {
+            None
+        } else {
+            Some(&self.model)
+        }
+    }
+    /// Text of the first choice.
+    ///
+    /// Returns `Error::EmptyResponse` when there are no choices or when the
+    /// content of the first choice contains no text.
+    pub fn first_text(&self) -> Result<&str, Error> {
+        let choice = self.choices.first().ok_or(Error::EmptyResponse)?;
+        match &choice.message.content {
+            Content::Text(text) => Ok(text),
+            Content::Collection(items) => items
+                .iter()
+                .find_map(|item| match item {
+                    SubContent::TextContent { text } => Some(text.as_str()),
+                    SubContent::ImageUrlContent { .. } => None,
+                })
+                .ok_or(Error::EmptyResponse),
+        }
+    }
+    /// Deserialize the text of the first choice, for example when requesting
+    /// `ResponseFormat::JsonObject` via `ChatConfig.response_format`.
+    ///
+    /// Returns `Error::Decode` with the text when it is not valid JSON for
+    /// `T`.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        let text = self.first_text()?;
+        serde_json::from_str(text)
+            .map_err(|e| Error::Decode(format!("content is not valid JSON ({e}): {text}")))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,22 +654,13 @@ pub struct ChatCompletionError {
     pub message: String,
 }
 
+/// Message of an error response without `error` or `detail`, see
+/// `crate::api_error`.
 fn extract_error(body: &Value) -> String {
-    if let Some(error) = body.get("error") {
-        if let Some(message) = error.get("message") {
-            return message
-                .as_str()
-                .unwrap_or(body.to_string().as_str())
-                .to_string();
-        }
-    }
-    if let Some(message) = body.get("message") {
-        return message
-            .as_str()
-            .unwrap_or(body.to_string().as_str())
-            .to_string();
+    match body.get("message").and_then(|message| message.as_str()) {
+        Some(message) => message.to_string(),
+        None => format!("Unknown error: {body}"),
     }
-    format!("Unknown error: {body}")
 }
 
 /// Response from the OpenAI API.
@@ -113,35 +675,75 @@ fn extract_error(body: &Value) -> String {
 /// walking the tightrope. The challenge is to build constraints that empower,
 /// not confine."
 pub struct ChatCompletionResponse {
-    status: u16,
-    resp: Bytes,
+    pub(crate) provider: Provider,
+    pub(crate) status: u16,
+    pub(crate) request_id: Option<String>,
+    pub(crate) rate_limit: Option<RateLimitInfo>,
+    pub(crate) resp: Bytes,
 }
 
 impl ChatCompletionResponse {
+    /// ID of the request from the `x-request-id` header.
+    ///
+    /// Also available when `structured` returns an error.
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+    /// Rate limits from the response headers, if the provider sent them.
+    pub fn rate_limit(&self) -> Option<&RateLimitInfo> {
+        self.rate_limit.as_ref()
+    }
     pub fn bytes(&self) -> &Bytes {
         &self.resp
     }
-    pub fn raw_value(&self) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    pub fn raw_value(&self) -> Result<Value, Error> {
         Ok(serde_json::from_slice::<Value>(&self.resp)?)
     }
-    pub fn structured(&self) -> Result<ChatCompletion, Box<dyn Error + Send + Sync>> {
-        let json = self.raw_value()?;
-        let text = json.to_string();
-        if text.is_empty() {
-            return Err(
-                format!("Received empty response with status code: {}", self.status).into(),
-            );
+    pub fn structured(&self) -> Result<ChatCompletion, Error> {
+        if self.resp.is_empty() {
+            return Err(Error::Api {
+                provider: self.provider.clone(),
+                status: self.status,
+                message: "Received empty response".to_string(),
+            });
         }
-        let json = match serde_json::from_str::<ChatCompletion>(&text) {
+        let is_success = (200..300).contains(&self.status);
+        let mut json = match self.raw_value() {
             Ok(json) => json,
-            Err(_e) => match serde_json::from_str::<Value>(&text) {
-                Ok(error) => return Err(extract_error(&error).into()),
-                Err(e) => {
-                    return Err(format!("Error parsing response: {} in text: '{}'", e, text).into())
-                }
-            },
+            // For example, an HTML page from a gateway.
+            Err(_) if !is_success => {
+                return Err(Error::Api {
+                    provider: self.provider.clone(),
+                    status: self.status,
+                    message: String::from_utf8_lossy(&self.resp).to_string(),
+                });
+            }
+            Err(e) => return Err(e),
         };
-        Ok(json)
+        if let Some(error) = crate::api_error(&self.provider, self.status, &json) {
+            return Err(error);
+        }
+        if !is_success {
+            return Err(Error::Api {
+                provider: self.provider.clone(),
+                status: self.status,
+                message: extract_error(&json),
+            });
+        }
+        if self.provider == Provider::Anthropic {
+            json = crate::anthropic::response(&json);
+        }
+        match serde_json::from_value::<ChatCompletion>(json.clone()) {
+            Ok(mut completion) => {
+                completion.request_id = self.request_id.clone();
+                Ok(completion)
+            }
+            Err(_e) => Err(Error::Api {
+                provider: self.provider.clone(),
+                status: self.status,
+                message: extract_error(&json),
+            }),
+        }
     }
 }
 
@@ -150,30 +752,272 @@ pub async fn chat_completion(
     key: &Key,
     model: &str,
     messages: &[Message],
-) -> Result<ChatCompletionResponse, Box<dyn Error + Send + Sync>> {
+) -> Result<ChatCompletionResponse, Error> {
+    let config = ChatConfig::default();
+    chat_completion_with_config(provider, key, &config, model, messages).await
+}
+
+/// Chat completion with sampling settings such as `temperature` or `max_tokens`.
+pub async fn chat_completion_with_config(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    messages: &[Message],
+) -> Result<ChatCompletionResponse, Error> {
     let stream = false;
-    let resp = request_chat_completion(provider, key, model, stream, messages).await?;
+    let resp = request_chat_completion(provider, key, config, model, stream, messages).await?;
     let status = resp.status();
     let chat_completion_response = ChatCompletionResponse {
+        provider: provider.clone(),
         status: status.into(),
+        request_id: crate::request_id(resp.headers()),
+        rate_limit: RateLimitInfo::from_headers(resp.headers()),
         resp: resp.bytes().await?,
     };
     Ok(chat_completion_response)
 }
 
+/// Send a chat completion for each of the `prompts`, with at most
+/// `concurrency` requests in flight.
+///
+/// The results are in the same order as the prompts. A failed request does
+/// not stop the others.
+pub async fn chat_completion_batch(
+    provider: &Provider,
+    key: &Key,
+    model: &str,
+    prompts: &[Vec<Message>],
+    concurrency: usize,
+) -> Vec<Result<ChatCompletionResponse, Error>> {
+    let config = ChatConfig::default();
+    chat_completion_batch_with_config(provider, key, &config, model, prompts, concurrency).await
+}
+
+/// Version of `chat_completion_batch` with sampling settings.
+pub async fn chat_completion_batch_with_config(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    prompts: &[Vec<Message>],
+    concurrency: usize,
+) -> Vec<Result<ChatCompletionResponse, Error>> {
+    let config = &ChatConfig {
+        retry: crate::fan_out_retry(&config.retry),
+        ..config.clone()
+    };
+    futures::stream::iter(prompts)
+        .map(|messages| chat_completion_with_config(provider, key, config, model, messages))
+        .buffered(concurrency.max(1))
+        .collect()
+        .await
+}
+
+/// Backend for chat completions, which can be replaced by a mock in tests.
+///
+/// `HttpBackend` sends the requests to the provider.
+pub trait ChatBackend {
+    fn chat_completion(
+        &self,
+        config: &ChatConfig,
+        model: &str,
+        messages: &[Message],
+    ) -> impl Future<Output = Result<ChatCompletion, Error>> + Send;
+}
+
+/// Messages of a multi-turn chat.
+///
+/// Keeps at most one system message, at the start, since most providers
+/// expect that. Pushing another system message appends its text to the
+/// existing one.
+#[derive(Clone, Debug, Default)]
+pub struct Conversation {
+    messages: Vec<Message>,
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push_system(&mut self, text: &str) {
+        match self.messages.first_mut() {
+            Some(first) if first.role == Role::System => {
+                first.content = Content::Text(format!("{}\n\n{text}", first.content));
+            }
+            _ => self.messages.insert(0, Message::system(text)),
+        }
+    }
+    pub fn push_user(&mut self, text: &str) {
+        self.messages.push(Message::user(text));
+    }
+    pub fn push_assistant(&mut self, text: &str) {
+        self.messages.push(Message::assistant(text));
+    }
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+    /// Request the next assistant message, append it, and return it.
+    pub async fn complete(
+        &mut self,
+        provider: &Provider,
+        key: &Key,
+        model: &str,
+    ) -> Result<Message, Error> {
+        let config = ChatConfig::default();
+        self.complete_with_config(provider, key, &config, model)
+            .await
+    }
+    /// Version of `complete` with sampling settings.
+    pub async fn complete_with_config(
+        &mut self,
+        provider: &Provider,
+        key: &Key,
+        config: &ChatConfig,
+        model: &str,
+    ) -> Result<Message, Error> {
+        let resp =
+            chat_completion_with_config(provider, key, config, model, &self.messages).await?;
+        let completion = resp.structured()?;
+        let message = match completion.choices.into_iter().next() {
+            Some(choice) => choice.message,
+            None => return Err(Error::EmptyResponse),
+        };
+        self.messages.push(message.clone());
+        Ok(message)
+    }
+}
+
+/// Whether the next provider should be tried after this failure.
+fn should_fall_back(error: &Error) -> bool {
+    match error {
+        Error::Http(e) => e.is_connect() || e.is_timeout(),
+        Error::Api { status, .. } => *status == 429 || (500..600).contains(status),
+        Error::Timeout | Error::MissingKey(_) | Error::InvalidConfig(_) => true,
+        _ => false,
+    }
+}
+
+/// Chat completion that tries each provider in order until one succeeds.
+///
+/// The model for each provider is looked up in `model_map`. The next provider
+/// is tried when the request times out, cannot connect, or returns status 429
+/// or 5xx, and also when no key or model is available for the provider. Other
+/// responses, such as status 400, are returned as is since another provider
+/// is unlikely to accept the same request.
+///
+/// Returns `Error::AllProvidersFailed` with the error of each provider when
+/// all providers fail.
+pub async fn chat_completion_fallback(
+    keys: &Keys,
+    providers: &[Provider],
+    model_map: &HashMap<Provider, String>,
+    messages: &[Message],
+) -> Result<ChatCompletionResponse, Error> {
+    let config = ChatConfig::default();
+    chat_completion_fallback_with_config(keys, providers, model_map, &config, messages).await
+}
+
+/// Version of `chat_completion_fallback` with sampling settings.
+pub async fn chat_completion_fallback_with_config(
+    keys: &Keys,
+    providers: &[Provider],
+    model_map: &HashMap<Provider, String>,
+    config: &ChatConfig,
+    messages: &[Message],
+) -> Result<ChatCompletionResponse, Error> {
+    let mut errors = vec![];
+    for provider in providers {
+        let result = match (keys.try_for_provider(provider), model_map.get(provider)) {
+            (Err(e), _) => Err(e),
+            (Ok(_), None) => Err(Error::InvalidConfig(format!(
+                "no model in model_map for {provider}"
+            ))),
+            (Ok(key), Some(model)) => {
+                chat_completion_with_config(provider, &key, config, model, messages).await
+            }
+        };
+        let error = match result {
+            Ok(resp) if resp.status == 429 || 500 <= resp.status => Error::Api {
+                provider: provider.clone(),
+                status: resp.status,
+                message: String::from_utf8_lossy(&resp.resp).to_string(),
+            },
+            Ok(resp) => return Ok(resp),
+            Err(e) => e,
+        };
+        if !should_fall_back(&error) {
+            return Err(error);
+        }
+        tracing::debug!("Falling back from {provider}: {error}");
+        errors.push((provider.clone(), error));
+    }
+    Err(Error::AllProvidersFailed(errors))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Delta {
     pub role: Option<String>,
     pub content: Option<String>,
+    /// Part of the reasoning, see `Message::reasoning`.
+    #[serde(
+        flatten,
+        deserialize_with = "crate::deserialize_reasoning",
+        serialize_with = "serialize_reasoning"
+    )]
+    pub reasoning: Option<String>,
+    /// Fragments of the tool calls, see `collect_stream` to assemble them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// Serialize `Delta::reasoning` as `reasoning`, if any.
+fn serialize_reasoning<S>(reasoning: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use serde::ser::SerializeMap;
+
+    let mut map = serializer.serialize_map(None)?;
+    if let Some(reasoning) = reasoning {
+        map.serialize_entry("reasoning", reasoning)?;
+    }
+    map.end()
+}
+
+/// Fragment of a tool call in a streaming chunk.
+///
+/// The id and name are only in the first fragment of a call, while the
+/// arguments are split over multiple fragments with the same `index`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolCallDelta {
+    pub index: u64,
+    pub id: Option<String>,
+    pub function: Option<FunctionDelta>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FunctionDelta {
+    pub name: Option<String>,
+    /// Part of the JSON-encoded arguments.
+    pub arguments: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChunkChoice {
     pub index: u64,
     pub delta: Delta,
+    /// Only set in the last chunk. See also `reason`.
     pub finish_reason: Option<String>,
 }
 
+impl ChunkChoice {
+    /// Typed version of `finish_reason`.
+    pub fn reason(&self) -> Option<FinishReason> {
+        self.finish_reason.as_deref().map(FinishReason::from)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatCompletionChunk {
     pub id: Option<String>,
@@ -184,34 +1028,199 @@ pub struct ChatCompletionChunk {
     pub choices: Vec<ChunkChoice>,
 }
 
-fn process_line(line: &str) -> Option<ChatCompletionChunk> {
-    if line.is_empty() {
+/// Usage in a streaming chunk.
+///
+/// Most providers send the usage in the last chunk, but Groq sends it inside
+/// `x_groq` and Anthropic splits it over multiple events.
+fn update_usage(provider: &Provider, json: &Value, usage: &mut Option<Usage>) {
+    if provider == &Provider::Anthropic {
+        crate::anthropic::stream_usage(json, usage);
+        return;
+    }
+    // OpenAI-compatible chunks may contain `"usage": null` next to `x_groq`.
+    let value = [json, &json["x_groq"]]
+        .into_iter()
+        .find_map(|json| json.get("usage").filter(|value| !value.is_null()));
+    let value = match value {
+        Some(value) => value,
+        None => return,
+    };
+    if let Ok(value) = serde_json::from_value::<Usage>(value.clone()) {
+        *usage = Some(value);
+    }
+}
+
+/// Chunk in a line of a server-sent events stream.
+///
+/// Only `data:` lines contain chunks. Blank lines between events, comments
+/// such as the `: keep-alive` or `: OPENROUTER PROCESSING` that some providers
+/// send while the model is busy, and other fields such as `event:` or `id:`
+/// are skipped.
+fn process_line(
+    provider: &Provider,
+    line: &str,
+    usage: &mut Option<Usage>,
+) -> Option<ChatCompletionChunk> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with(':') {
         return None;
     }
 
-    if let Some(json_str) = line.strip_prefix("data: ") {
+    if let Some(json_str) = line.strip_prefix("data:") {
+        let json_str = json_str.trim_start();
         if json_str == "[DONE]" {
             return None;
         }
-        match serde_json::from_str::<ChatCompletionChunk>(json_str) {
-            Ok(chunk) => Some(chunk),
-            Err(_) => None,
+        let json = serde_json::from_str::<Value>(json_str).ok()?;
+        update_usage(provider, &json, usage);
+        if provider == &Provider::Anthropic {
+            return crate::anthropic::stream_event(&json);
         }
+        serde_json::from_value::<ChatCompletionChunk>(json).ok()
     } else {
         None
     }
 }
 
-pub async fn stream_chat_completion(
-    provider: &Provider,
-    key: &Key,
-    model: &str,
-    messages: &[Message],
-) -> Result<Pin<Box<dyn Stream<Item = ChatCompletionChunk> + Send>>, Box<dyn Error + Send + Sync>> {
-    let resp = request_chat_completion(provider, key, model, true, messages).await?;
+/// Event in a streaming chat completion.
+#[derive(Debug)]
+pub enum StreamEvent {
+    Chunk(ChatCompletionChunk),
+    /// Last event of the stream.
+    ///
+    /// Contains the token usage when the provider reported it. For OpenAI, the
+    /// usage is only reported when `stream_options` is set to
+    /// `{"include_usage": true}` via `ChatConfig.other`.
+    StreamEnd {
+        usage: Option<Usage>,
+    },
+}
+
+impl From<ChatCompletionChunk> for StreamEvent {
+    fn from(chunk: ChatCompletionChunk) -> Self {
+        StreamEvent::Chunk(chunk)
+    }
+}
+
+/// Choice that is being assembled from the chunks.
+#[derive(Default)]
+struct PartialChoice {
+    content: String,
+    reasoning: Option<String>,
+    /// Id, name, and arguments by index of the tool call.
+    tool_calls: BTreeMap<u64, (String, String, String)>,
+    finish_reason: Option<String>,
+}
+
+/// Consume a streaming chat completion into the complete `ChatCompletion`.
+///
+/// Concatenates the content and assembles the tool calls from their
+/// fragments, so that the result is the same as that of the non-streaming
+/// `chat_completion`. Accepts the stream of `stream_chat_completion` as well
+/// as that of `stream_chat_completion_events`, which also contains the usage.
+///
+/// Returns `Error::EmptyResponse` when the stream contains no chunks. Like in
+/// `chat_completion`, tool call arguments that are not valid JSON are kept as
+/// a string.
+pub async fn collect_stream<S, T>(stream: S) -> Result<ChatCompletion, Error>
+where
+    S: Stream<Item = T>,
+    T: Into<StreamEvent>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut first: Option<ChatCompletionChunk> = None;
+    let mut choices: BTreeMap<u64, PartialChoice> = BTreeMap::new();
+    let mut usage = None;
+    while let Some(event) = stream.next().await {
+        let mut chunk = match event.into() {
+            StreamEvent::Chunk(chunk) => chunk,
+            StreamEvent::StreamEnd { usage: end } => {
+                usage = end;
+                continue;
+            }
+        };
+        for choice in chunk.choices.drain(..) {
+            let partial = choices.entry(choice.index).or_default();
+            if let Some(content) = choice.delta.content {
+                partial.content.push_str(&content);
+            }
+            if let Some(reasoning) = choice.delta.reasoning {
+                partial
+                    .reasoning
+                    .get_or_insert_with(String::new)
+                    .push_str(&reasoning);
+            }
+            for fragment in choice.delta.tool_calls.into_iter().flatten() {
+                let (id, name, arguments) = partial.tool_calls.entry(fragment.index).or_default();
+                if let Some(fragment_id) = fragment.id {
+                    *id = fragment_id;
+                }
+                if let Some(function) = fragment.function {
+                    if let Some(fragment_name) = function.name {
+                        *name = fragment_name;
+                    }
+                    if let Some(fragment_arguments) = function.arguments {
+                        arguments.push_str(&fragment_arguments);
+                    }
+                }
+            }
+            if choice.finish_reason.is_some() {
+                partial.finish_reason = choice.finish_reason;
+            }
+        }
+        match &mut first {
+            // Anthropic only sends the model in the first event.
+            Some(first) if first.model.is_empty() => first.model = chunk.model,
+            Some(_) => (),
+            None => first = Some(chunk),
+        }
+    }
+    let first = first.ok_or(Error::EmptyResponse)?;
+    let mut out = vec![];
+    for (index, partial) in choices {
+        let mut tool_calls = vec![];
+        for (id, name, arguments) in partial.tool_calls.into_values() {
+            let arguments = if arguments.trim().is_empty() {
+                Value::Object(Default::default())
+            } else {
+                serde_json::from_str(&arguments).unwrap_or(Value::String(arguments))
+            };
+            tool_calls.push(crate::ToolCall {
+                id,
+                name,
+                arguments,
+            });
+        }
+        let mut message = Message::assistant(&partial.content);
+        message.reasoning = partial.reasoning;
+        if !tool_calls.is_empty() {
+            message.tool_calls = Some(tool_calls);
+        }
+        out.push(Choice {
+            index,
+            message,
+            logprobs: None,
+            finish_reason: partial.finish_reason,
+        });
+    }
+    Ok(ChatCompletion {
+        id: first.id,
+        request_id: None,
+        object: "chat.completion".to_string(),
+        created: first.created,
+        model: first.model,
+        system_fingerprint: first.system_fingerprint,
+        choices: out,
+        service_tier: None,
+        usage,
+    })
+}
 
-    let stream = stream! {
+fn events(provider: &Provider, resp: Response) -> impl Stream<Item = StreamEvent> {
+    let provider = provider.clone();
+    stream! {
         let mut buffer = String::new();
+        let mut usage = None;
         let mut byte_stream = resp.bytes_stream();
 
         while let Some(chunk) = byte_stream.next().await {
@@ -234,18 +1243,60 @@ pub async fn stream_chat_completion(
                     buffer.push_str(line);
                     continue;
                 }
-                if let Some(chunk) = process_line(line) {
-                    yield chunk;
+                if let Some(chunk) = process_line(&provider, line, &mut usage) {
+                    yield StreamEvent::Chunk(chunk);
                 }
             }
         }
 
         if !buffer.is_empty() {
-            if let Some(chunk) = process_line(&buffer) {
-                yield chunk;
+            if let Some(chunk) = process_line(&provider, &buffer, &mut usage) {
+                yield StreamEvent::Chunk(chunk);
             }
         }
-    };
+        yield StreamEvent::StreamEnd { usage };
+    }
+}
 
+pub async fn stream_chat_completion(
+    provider: &Provider,
+    key: &Key,
+    model: &str,
+    messages: &[Message],
+) -> Result<Pin<Box<dyn Stream<Item = ChatCompletionChunk> + Send>>, Error> {
+    let config = ChatConfig::default();
+    stream_chat_completion_with_config(provider, key, &config, model, messages).await
+}
+
+/// Streaming chat completion with settings such as `temperature` or `max_tokens`.
+pub async fn stream_chat_completion_with_config(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    messages: &[Message],
+) -> Result<Pin<Box<dyn Stream<Item = ChatCompletionChunk> + Send>>, Error> {
+    let resp = request_chat_completion(provider, key, config, model, true, messages).await?;
+    let stream = events(provider, resp).filter_map(|event| async move {
+        match event {
+            StreamEvent::Chunk(chunk) => Some(chunk),
+            StreamEvent::StreamEnd { .. } => None,
+        }
+    });
     Ok(Box::pin(stream))
 }
+
+/// Streaming chat completion that ends with a `StreamEvent::StreamEnd`.
+///
+/// Unlike `stream_chat_completion_with_config`, this reports the token usage
+/// in the same way for all providers.
+pub async fn stream_chat_completion_events(
+    provider: &Provider,
+    key: &Key,
+    config: &ChatConfig,
+    model: &str,
+    messages: &[Message],
+) -> Result<Pin<Box<dyn Stream<Item = StreamEvent> + Send>>, Error> {
+    let resp = request_chat_completion(provider, key, config, model, true, messages).await?;
+    Ok(Box::pin(events(provider, resp)))
+}
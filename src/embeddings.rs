@@ -0,0 +1,204 @@
+//! Embeddings.
+//!
+//! Functionality related to vector embeddings.
+
+use crate::request_headers;
+use crate::Error;
+use crate::Key;
+use crate::Provider;
+use crate::RetryConfig;
+use bytes::Bytes;
+use reqwest;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::Instrument;
+
+fn address(provider: &Provider, base_url: Option<&str>) -> String {
+    let base_url = crate::openai_base_url(provider, base_url);
+    format!("{}/embeddings", base_url)
+}
+
+/// Embeddings config.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct EmbedConfig {
+    /// Number of dimensions of the output vectors, for models that support it.
+    pub dimensions: Option<u32>,
+    /// See [common options](crate#common-options).
+    pub user: Option<String>,
+    pub other: Option<HashMap<String, Value>>,
+    /// See [common options](crate#common-options).
+    pub retry: Option<RetryConfig>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Embedding {
+    pub index: u64,
+    #[serde(rename = "embedding")]
+    pub vector: Vec<f32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub total_tokens: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Embeddings {
+    #[serde(rename = "data")]
+    pub embeddings: Vec<Embedding>,
+    pub usage: Option<Usage>,
+}
+
+pub struct EmbeddingResponse {
+    provider: Provider,
+    status: u16,
+    resp: Bytes,
+}
+
+impl EmbeddingResponse {
+    pub fn bytes(&self) -> &Bytes {
+        &self.resp
+    }
+    pub fn raw_value(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_slice::<Value>(&self.resp)?)
+    }
+    pub fn structured(&self) -> Result<Embeddings, Error> {
+        let resp = self.raw_value()?;
+        if let Some(error) = crate::api_error(&self.provider, self.status, &resp) {
+            return Err(error);
+        }
+        match serde_json::from_value::<Embeddings>(resp.clone()) {
+            Ok(embeddings) => Ok(embeddings),
+            Err(e) => Err(Error::Decode(format!("{e} in response:\n{resp}"))),
+        }
+    }
+}
+
+/// Request embeddings for the inputs.
+///
+/// The index of each embedding corresponds to the index in `inputs`.
+pub async fn embed(
+    provider: &Provider,
+    key: &Key,
+    model: &str,
+    inputs: &[String],
+) -> Result<EmbeddingResponse, Error> {
+    let config = EmbedConfig::default();
+    embed_with_config(provider, key, &config, model, inputs).await
+}
+
+/// Request embeddings with settings such as `dimensions` or retries.
+pub async fn embed_with_config(
+    provider: &Provider,
+    key: &Key,
+    config: &EmbedConfig,
+    model: &str,
+    inputs: &[String],
+) -> Result<EmbeddingResponse, Error> {
+    let address = address(provider, config.base_url.as_deref());
+    let mut body = serde_json::json!({
+        "model": model,
+        "input": inputs,
+        "encoding_format": "float",
+    });
+    if let Some(dimensions) = config.dimensions {
+        body["dimensions"] = Value::from(dimensions);
+    }
+    if let Some(user) = &config.user {
+        body["user"] = Value::from(user.clone());
+    }
+    if let Some(other) = &config.other {
+        for (key, value) in other {
+            body[key] = value.clone();
+        }
+    }
+    tracing::debug!("Requesting embeddings for {} inputs", inputs.len());
+    let mut headers = request_headers(key)?;
+    crate::insert_idempotency_key(&mut headers, provider, config.retry.as_ref())?;
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    let client = crate::client(&config.client)?;
+    let request = client.post(address).headers(headers).json(&body);
+    let span = crate::request_span("embeddings", provider, Some(model));
+    let resp = crate::send(request, config.retry.as_ref(), config.timeout)
+        .instrument(span)
+        .await?;
+    let embedding_response = EmbeddingResponse {
+        provider: provider.clone(),
+        status: resp.status().into(),
+        resp: resp.bytes().await?,
+    };
+    Ok(embedding_response)
+}
+
+/// Request embeddings in chunks of at most `batch_size` inputs.
+///
+/// Providers limit the number of inputs per request, so this sends multiple
+/// requests and combines the results. Indexes and usage are combined as if
+/// only one request was sent.
+pub async fn embed_batch(
+    provider: &Provider,
+    key: &Key,
+    config: &EmbedConfig,
+    model: &str,
+    inputs: &[String],
+    batch_size: usize,
+) -> Result<Embeddings, Error> {
+    let config = &EmbedConfig {
+        retry: crate::fan_out_retry(&config.retry),
+        ..config.clone()
+    };
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    let mut usage: Option<Usage> = None;
+    for (i, chunk) in inputs.chunks(batch_size.max(1)).enumerate() {
+        let offset = (i * batch_size.max(1)) as u64;
+        let resp = embed_with_config(provider, key, config, model, chunk)
+            .await?
+            .structured()?;
+        embeddings.extend(resp.embeddings.into_iter().map(|embedding| Embedding {
+            index: embedding.index + offset,
+            vector: embedding.vector,
+        }));
+        if let Some(chunk_usage) = resp.usage {
+            usage = Some(match usage {
+                Some(usage) => Usage {
+                    prompt_tokens: usage.prompt_tokens + chunk_usage.prompt_tokens,
+                    total_tokens: usage.total_tokens + chunk_usage.total_tokens,
+                },
+                None => chunk_usage,
+            });
+        }
+    }
+    Ok(Embeddings { embeddings, usage })
+}
+
+/// Cosine similarity between two vectors.
+///
+/// Returns a value between -1 and 1, where 1 means that the vectors point in
+/// the same direction. Returns 0 when one of the vectors has zero length and
+/// `None` when the vectors have different lengths, for example because they
+/// were created by different models or with different `dimensions`.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return Some(0.0);
+    }
+    Some(dot / (norm_a * norm_b))
+}
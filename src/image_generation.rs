@@ -0,0 +1,280 @@
+//! Image generation.
+//!
+//! Functionality related to generating images via OpenAI-compatible
+//! `/images/generations` endpoints such as DALL-E. Together AI uses the same
+//! endpoint with a slightly different body and Fireworks uses its own
+//! endpoint; the differences are handled here.
+
+use crate::request_headers;
+use crate::Error;
+use crate::Key;
+use crate::Provider;
+use crate::RetryConfig;
+use bytes::Bytes;
+use reqwest;
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::Instrument;
+
+fn address(provider: &Provider, base_url: Option<&str>, model: &str) -> Result<String, Error> {
+    let base_url = crate::openai_base_url(provider, base_url);
+    match provider {
+        Provider::DeepInfra
+        | Provider::Google
+        | Provider::OpenAI
+        | Provider::Other(_)
+        | Provider::TogetherAI => Ok(format!("{}/images/generations", base_url)),
+        // For example, "accounts/fireworks/models/stable-diffusion-xl-1024-v1-0".
+        Provider::Fireworks => Ok(format!("{}/image_generation/{model}", base_url)),
+        _ => Err(Error::UnsupportedProvider(provider.clone())),
+    }
+}
+
+/// Width and height from a size such as "1024x768".
+fn width_and_height(size: &str) -> Result<(u32, u32), Error> {
+    let parsed = size
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+    parsed.ok_or(Error::InvalidConfig(format!(
+        "size should be in the form WIDTHxHEIGHT, got {size}"
+    )))
+}
+
+fn request_body(
+    provider: &Provider,
+    config: &ImageGenConfig,
+    model: &str,
+    prompt: &str,
+) -> Result<Value, Error> {
+    let is_native = matches!(provider, Provider::Fireworks | Provider::TogetherAI);
+    if config.quality.is_some() && is_native {
+        return Err(Error::InvalidConfig(format!(
+            "quality is not supported by {provider}"
+        )));
+    }
+    let mut body = serde_json::json!({
+        "prompt": prompt,
+    });
+    if provider != &Provider::Fireworks {
+        body["model"] = Value::from(model);
+    }
+    if let Some(size) = &config.size {
+        if is_native {
+            let (width, height) = width_and_height(size)?;
+            body["width"] = Value::from(width);
+            body["height"] = Value::from(height);
+        } else {
+            body["size"] = Value::from(size.clone());
+        }
+    }
+    if let Some(quality) = &config.quality {
+        body["quality"] = Value::from(quality.clone());
+    }
+    if let Some(n) = config.n {
+        let name = if provider == &Provider::Fireworks {
+            "samples"
+        } else {
+            "n"
+        };
+        body[name] = Value::from(n);
+    }
+    if let Some(response_format) = config.response_format {
+        if provider == &Provider::Fireworks {
+            // Fireworks always returns base64 images.
+            if response_format == ImageFormat::Url {
+                return Err(Error::InvalidConfig(
+                    "url response format is not supported by Fireworks".to_string(),
+                ));
+            }
+        } else if provider == &Provider::TogetherAI {
+            let response_format = match response_format {
+                ImageFormat::Url => "url",
+                ImageFormat::B64Json => "base64",
+            };
+            body["response_format"] = Value::from(response_format);
+        } else {
+            body["response_format"] = serde_json::to_value(response_format)?;
+        }
+    }
+    if let Some(user) = &config.user {
+        body["user"] = Value::from(user.clone());
+    }
+    if let Some(other) = &config.other {
+        for (key, value) in other {
+            body[key] = value.clone();
+        }
+    }
+    Ok(body)
+}
+
+fn decode(b64_json: &str) -> Result<ImageData, Error> {
+    match crate::base64_decode(b64_json) {
+        Ok(bytes) => Ok(ImageData::Bytes(Bytes::from(bytes))),
+        Err(e) => Err(Error::Decode(format!("invalid base64 image: {e}"))),
+    }
+}
+
+/// How the generated images are returned.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    /// A URL to the image, which is usually only valid for a limited time.
+    Url,
+    /// The image itself, which is decoded into bytes.
+    B64Json,
+}
+
+/// Image generation config.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ImageGenConfig {
+    /// For example, "1024x1024".
+    pub size: Option<String>,
+    /// For example, "standard" or "hd".
+    pub quality: Option<String>,
+    /// Number of images to generate.
+    pub n: Option<u32>,
+    pub response_format: Option<ImageFormat>,
+    /// See [common options](crate#common-options).
+    pub user: Option<String>,
+    pub other: Option<HashMap<String, Value>>,
+    /// See [common options](crate#common-options).
+    pub retry: Option<RetryConfig>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImageData {
+    Url(String),
+    Bytes(Bytes),
+}
+
+#[derive(Clone, Debug)]
+pub struct GeneratedImage {
+    pub data: ImageData,
+    /// Prompt that was used to generate the image, if the provider rewrote it.
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Images {
+    pub created: Option<u64>,
+    pub images: Vec<GeneratedImage>,
+}
+
+pub struct ImageResponse {
+    provider: Provider,
+    status: u16,
+    resp: Bytes,
+}
+
+impl ImageResponse {
+    pub fn bytes(&self) -> &Bytes {
+        &self.resp
+    }
+    pub fn raw_value(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_slice::<Value>(&self.resp)?)
+    }
+    pub fn structured(&self) -> Result<Images, Error> {
+        let resp = match self.raw_value() {
+            Ok(resp) => resp,
+            Err(_) if !(200..300).contains(&self.status) => {
+                return Err(Error::Api {
+                    provider: self.provider.clone(),
+                    status: self.status,
+                    message: String::from_utf8_lossy(&self.resp).to_string(),
+                });
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(error) = crate::api_error(&self.provider, self.status, &resp) {
+            return Err(error);
+        }
+        // Fireworks returns an array such as `[{"base64": "...", "seed": 1}]`.
+        if let Some(data) = resp.as_array() {
+            let mut images = Vec::with_capacity(data.len());
+            for image in data {
+                let b64 = match image["base64"].as_str() {
+                    Some(b64) => b64,
+                    None => return Err(Error::Decode(format!("no base64 in: {image}"))),
+                };
+                images.push(GeneratedImage {
+                    data: decode(b64)?,
+                    revised_prompt: None,
+                });
+            }
+            return Ok(Images {
+                created: None,
+                images,
+            });
+        }
+        let data = match resp["data"].as_array() {
+            Some(data) => data,
+            None => return Err(Error::Decode(format!("no data array in response: {resp}"))),
+        };
+        let mut images = Vec::with_capacity(data.len());
+        for image in data {
+            let data = if let Some(b64_json) = image["b64_json"].as_str() {
+                decode(b64_json)?
+            } else if let Some(url) = image["url"].as_str() {
+                ImageData::Url(url.to_string())
+            } else {
+                return Err(Error::Decode(format!("no url or b64_json in: {image}")));
+            };
+            images.push(GeneratedImage {
+                data,
+                revised_prompt: image["revised_prompt"].as_str().map(|p| p.to_string()),
+            });
+        }
+        Ok(Images {
+            created: resp["created"].as_u64(),
+            images,
+        })
+    }
+}
+
+/// Generate images from a prompt.
+///
+/// For Fireworks, the model is part of the address, for example
+/// "accounts/fireworks/models/stable-diffusion-xl-1024-v1-0".
+pub async fn generate(
+    provider: &Provider,
+    key: &Key,
+    config: &ImageGenConfig,
+    model: &str,
+    prompt: &str,
+) -> Result<ImageResponse, Error> {
+    let address = address(provider, config.base_url.as_deref(), model)?;
+    let body = request_body(provider, config, model, prompt)?;
+    tracing::debug!("Requesting image generation: {body}");
+    let mut headers = request_headers(key)?;
+    if provider == &Provider::Fireworks {
+        // Otherwise, the image is returned as raw bytes.
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+    }
+    crate::insert_idempotency_key(&mut headers, provider, config.retry.as_ref())?;
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    let client = crate::client(&config.client)?;
+    let request = client.post(address).headers(headers).json(&body);
+    let span = crate::request_span("image_generation", provider, Some(model));
+    let resp = crate::send(request, config.retry.as_ref(), config.timeout)
+        .instrument(span)
+        .await?;
+    let image_response = ImageResponse {
+        provider: provider.clone(),
+        status: resp.status().into(),
+        resp: resp.bytes().await?,
+    };
+    Ok(image_response)
+}
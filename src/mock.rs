@@ -0,0 +1,105 @@
+//! Offline backend with canned responses, for examples and tests without a
+//! key.
+//!
+//! The responses are parsed by exactly the same code as the responses of a
+//! real provider.
+
+use crate::chat::ChatBackend;
+use crate::chat::ChatCompletion;
+use crate::chat::ChatCompletionResponse;
+use crate::chat::ChatConfig;
+use crate::text_to_speech::Speech;
+use crate::text_to_speech::SpeechResponse;
+use crate::text_to_speech::TTSConfig;
+use crate::text_to_speech::TtsBackend;
+use crate::Error;
+use crate::Message;
+use crate::Provider;
+use bytes::Bytes;
+use serde_json::json;
+
+/// Text of every chat completion.
+const TEXT: &str = "hello world";
+
+/// Backend that answers without sending any requests.
+///
+/// Implements `chat::ChatBackend` and `text_to_speech::TtsBackend`. Chat
+/// completions answer "hello world" and text to speech returns a short silent
+/// mp3.
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend;
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+fn provider() -> Provider {
+    Provider::Other("mock".to_string())
+}
+
+impl ChatBackend for MockBackend {
+    async fn chat_completion(
+        &self,
+        _config: &ChatConfig,
+        model: &str,
+        _messages: &[Message],
+    ) -> Result<ChatCompletion, Error> {
+        let body = json!({
+            "id": "mock",
+            "object": "chat.completion",
+            "created": 0,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": TEXT},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3}
+        });
+        let resp = ChatCompletionResponse {
+            provider: provider(),
+            status: 200,
+            request_id: Some("mock".to_string()),
+            rate_limit: None,
+            resp: Bytes::from(body.to_string()),
+        };
+        resp.structured()
+    }
+}
+
+impl TtsBackend for MockBackend {
+    async fn tts(
+        &self,
+        _config: &TTSConfig,
+        _model: Option<&str>,
+        _text: &str,
+    ) -> Result<Speech, Error> {
+        let resp = SpeechResponse {
+            provider: provider(),
+            status: 200,
+            // The audio is always mp3.
+            output_format: None,
+            rate_limit: None,
+            content_type: Some("audio/mpeg".to_string()),
+            resp: silence(),
+        };
+        resp.structured()
+    }
+}
+
+/// Silent mono mp3 of about a quarter of a second.
+///
+/// Consists of MPEG-1 Layer III frames at 32 kbps and 44.1 kHz without any
+/// audio data, which decoders play as silence.
+fn silence() -> Bytes {
+    // 144 * 32000 / 44100 bytes per frame.
+    const FRAME_LEN: usize = 104;
+    const FRAMES: usize = 10;
+    let mut audio = vec![0; FRAME_LEN * FRAMES];
+    for frame in audio.chunks_mut(FRAME_LEN) {
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x10, 0xC0]);
+    }
+    Bytes::from(audio)
+}
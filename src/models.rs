@@ -3,25 +3,128 @@
 //! Functionality related to requesting available models.
 
 use crate::request_headers;
+use crate::Error;
 use crate::Key;
 use crate::Provider;
-use reqwest;
+use bytes::Bytes;
 use serde::Deserialize;
 use serde_json::Value;
-use std::error::Error;
+use std::collections::HashMap;
+use tracing::Instrument;
 
-fn address(provider: &Provider) -> String {
-    let base_url = crate::openai_base_url(provider);
-    format!("{}/models", base_url)
+/// Provider-specific identifiers of models with a canonical name.
+///
+/// The same model is often named differently per provider, for example
+/// "meta-llama/Llama-3.3-70B-Instruct" on DeepInfra and
+/// "llama-3.3-70b-versatile" on Groq. Names without a mapping, or without a
+/// mapping for the provider, are returned unchanged by `resolve`.
+#[derive(Clone, Debug, Default)]
+pub struct ModelAlias {
+    aliases: HashMap<String, HashMap<Provider, String>>,
+}
+
+impl ModelAlias {
+    /// Mapping without any aliases.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Mapping with aliases for commonly hosted models, such as
+    /// "llama-3.3-70b" and "llama-3.1-8b".
+    pub fn builtin() -> Self {
+        let mut aliases = Self::new();
+        let llama_3_3_70b = [
+            (Provider::Cerebras, "llama-3.3-70b"),
+            (Provider::DeepInfra, "meta-llama/Llama-3.3-70B-Instruct"),
+            (
+                Provider::Fireworks,
+                "accounts/fireworks/models/llama-v3p3-70b-instruct",
+            ),
+            (Provider::FriendliAI, "meta-llama-3.3-70b-instruct"),
+            (Provider::Groq, "llama-3.3-70b-versatile"),
+            (Provider::Hyperbolic, "meta-llama/Llama-3.3-70B-Instruct"),
+            (Provider::Nebius, "meta-llama/Llama-3.3-70B-Instruct"),
+            (Provider::Novita, "meta-llama/llama-3.3-70b-instruct"),
+            (Provider::SambaNova, "Meta-Llama-3.3-70B-Instruct"),
+            (
+                Provider::TogetherAI,
+                "meta-llama/Llama-3.3-70B-Instruct-Turbo",
+            ),
+        ];
+        for (provider, model) in llama_3_3_70b {
+            aliases.insert("llama-3.3-70b", provider, model);
+        }
+        let llama_3_1_8b = [
+            (Provider::Cerebras, "llama3.1-8b"),
+            (Provider::DeepInfra, "meta-llama/Meta-Llama-3.1-8B-Instruct"),
+            (
+                Provider::Fireworks,
+                "accounts/fireworks/models/llama-v3p1-8b-instruct",
+            ),
+            (Provider::Groq, "llama-3.1-8b-instant"),
+            (
+                Provider::Hyperbolic,
+                "meta-llama/Meta-Llama-3.1-8B-Instruct",
+            ),
+            (Provider::Novita, "meta-llama/llama-3.1-8b-instruct"),
+            (Provider::SambaNova, "Meta-Llama-3.1-8B-Instruct"),
+            (
+                Provider::TogetherAI,
+                "meta-llama/Meta-Llama-3.1-8B-Instruct-Turbo",
+            ),
+        ];
+        for (provider, model) in llama_3_1_8b {
+            aliases.insert("llama-3.1-8b", provider, model);
+        }
+        aliases
+    }
+    /// Map `alias` to `model` for the provider, replacing an existing mapping.
+    pub fn insert(&mut self, alias: &str, provider: Provider, model: &str) {
+        self.aliases
+            .entry(alias.to_string())
+            .or_default()
+            .insert(provider, model.to_string());
+    }
+    /// Identifier of the model for the provider.
+    ///
+    /// Returns `model` unchanged when it is not a known alias for the provider.
+    pub fn resolve<'a>(&'a self, provider: &Provider, model: &'a str) -> &'a str {
+        match self
+            .aliases
+            .get(model)
+            .and_then(|models| models.get(provider))
+        {
+            Some(resolved) => resolved,
+            None => model,
+        }
+    }
+}
+
+fn address(provider: &Provider) -> Result<String, Error> {
+    match provider {
+        Provider::Amazon | Provider::Azure | Provider::ElevenLabs => {
+            Err(Error::UnsupportedProvider(provider.clone()))
+        }
+        _ => {
+            let base_url = crate::openai_base_url(provider, None);
+            Ok(format!("{}/models", base_url))
+        }
+    }
 }
 
 pub struct ModelsResponse {
-    resp: Value,
+    provider: Provider,
+    status: u16,
+    resp: Bytes,
+    /// Parsed body, which is `Value::Null` when the body is not JSON.
+    value: Value,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Model {
     pub id: String,
+    pub owned_by: Option<String>,
+    /// Unix timestamp (in seconds) of when the model was created.
+    pub created: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,33 +139,91 @@ impl Models {
 }
 
 impl ModelsResponse {
-    pub fn raw(&self) -> &Value {
+    pub fn bytes(&self) -> &Bytes {
         &self.resp
     }
-    pub fn structured(&self) -> Result<Models, Box<dyn Error + Send + Sync>> {
-        let data = self.resp.get("data").unwrap().as_array().unwrap();
-        Ok(Models {
-            models: data
-                .iter()
-                .map(|model| serde_json::from_value(model.clone()).unwrap())
-                .collect(),
-        })
+    pub fn raw_value(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_slice::<Value>(&self.resp)?)
+    }
+    /// Parsed body, which is `Value::Null` when the body is not JSON.
+    ///
+    /// See also `raw_value`, which returns an error in that case.
+    pub fn raw(&self) -> &Value {
+        &self.value
+    }
+    pub fn structured(&self) -> Result<Models, Error> {
+        if self.value.is_null() && !(200..300).contains(&self.status) {
+            return Err(Error::Api {
+                provider: self.provider.clone(),
+                status: self.status,
+                message: String::from_utf8_lossy(&self.resp).to_string(),
+            });
+        }
+        if let Some(error) = crate::api_error(&self.provider, self.status, &self.value) {
+            return Err(error);
+        }
+        let data = match self.value.get("data").and_then(|data| data.as_array()) {
+            Some(data) => data,
+            None => {
+                return Err(Error::Decode(format!(
+                    "no data array in response: {}",
+                    String::from_utf8_lossy(&self.resp)
+                )))
+            }
+        };
+        let models = data
+            .iter()
+            .map(|model| serde_json::from_value(model.clone()))
+            .collect::<Result<Vec<Model>, _>>()?;
+        Ok(Models { models })
     }
 }
 
-pub async fn models(
-    provider: &Provider,
-    key: &Key,
-) -> Result<ModelsResponse, Box<dyn Error + Send + Sync>> {
-    let address = address(provider);
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(address)
-        .headers(request_headers(key)?)
-        .send()
-        .await?;
+pub async fn models(provider: &Provider, key: &Key) -> Result<ModelsResponse, Error> {
+    let address = address(provider)?;
+    let client = crate::client_builder().build()?;
+    let request = client.get(address).headers(request_headers(key)?);
+    let span = crate::request_span("models", &key.provider, None);
+    let resp = crate::send(request, None, None).instrument(span).await?;
+    let status = resp.status().into();
+    let resp = resp.bytes().await?;
     let models_response = ModelsResponse {
-        resp: resp.json::<Value>().await?,
+        provider: provider.clone(),
+        status,
+        value: serde_json::from_slice(&resp).unwrap_or(Value::Null),
+        resp,
     };
     Ok(models_response)
 }
+
+pub(crate) async fn validate(key: &Key) -> Result<(), Error> {
+    let address = if key.provider == Provider::ElevenLabs {
+        format!("{}/v1/user", key.provider.domain())
+    } else {
+        address(&key.provider)?
+    };
+    let client = crate::client_builder().build()?;
+    let request = client.get(address).headers(request_headers(key)?);
+    let span = crate::request_span("models", &key.provider, None);
+    let resp = crate::send(request, None, None).instrument(span).await?;
+    let status = resp.status();
+    if status == 401 || status == 403 {
+        return Err(Error::Unauthorized(key.provider.clone()));
+    }
+    if !status.is_success() {
+        return Err(Error::Api {
+            provider: key.provider.clone(),
+            status: status.into(),
+            message: resp.text().await?,
+        });
+    }
+    Ok(())
+}
+
+/// List the models that are available at the provider.
+///
+/// This is a shorthand for `models(provider, key).await?.structured()`.
+pub async fn list_models(provider: &Provider, key: &Key) -> Result<Vec<Model>, Error> {
+    let models = models(provider, key).await?.structured()?;
+    Ok(models.models)
+}
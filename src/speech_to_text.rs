@@ -0,0 +1,359 @@
+//! Speech-to-text.
+//!
+//! Functionality related to speech-to-text (transcription and translation).
+
+use crate::request_headers;
+use crate::Error;
+use crate::Key;
+use crate::Provider;
+use async_stream::stream;
+use bytes::Bytes;
+use futures::Stream;
+use futures::StreamExt;
+use reqwest;
+use reqwest::multipart::Form;
+use reqwest::multipart::Part;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::pin::Pin;
+use std::time::Duration;
+use tracing::Instrument;
+
+/// Speech-to-text config.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct STTConfig {
+    /// Language of the audio in ISO-639-1 format, for example "en".
+    pub language: Option<String>,
+    /// Text to guide the style of the transcription.
+    pub prompt: Option<String>,
+    /// One of "json", "text", or "verbose_json".
+    pub response_format: Option<String>,
+    /// Timestamps to include, "word" and/or "segment".
+    ///
+    /// Requires `response_format` "verbose_json".
+    pub timestamp_granularities: Option<Vec<String>>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
+}
+
+fn address(key: &Key, config: &STTConfig, path: &str) -> Result<String, Error> {
+    match key.provider {
+        Provider::DeepInfra | Provider::OpenAI | Provider::TogetherAI => Ok(format!(
+            "{}/audio/{path}",
+            crate::openai_base_url(&key.provider, config.base_url.as_deref())
+        )),
+        _ => Err(Error::UnsupportedProvider(key.provider.clone())),
+    }
+}
+
+fn default_model(provider: &Provider) -> &'static str {
+    match provider {
+        Provider::DeepInfra | Provider::TogetherAI => "openai/whisper-large-v3",
+        _ => "whisper-1",
+    }
+}
+
+/// Maximum size of the audio upload, which is the limit of the OpenAI API.
+pub const MAX_AUDIO_BYTES: usize = 25 * 1024 * 1024;
+
+/// Guess the file extension from the magic bytes of the audio.
+///
+/// The providers use the file name of the upload to determine the format.
+/// Returns `None` for formats that Whisper does not support.
+fn file_extension(audio: &[u8]) -> Option<&'static str> {
+    let extension = if audio.starts_with(b"RIFF") {
+        "wav"
+    } else if audio.starts_with(b"OggS") {
+        "ogg"
+    } else if audio.starts_with(b"fLaC") {
+        "flac"
+    } else if audio.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        "webm"
+    } else if audio.len() > 8 && &audio[4..8] == b"ftyp" {
+        "m4a"
+    } else if audio.starts_with(b"ID3")
+        || (audio.len() > 1 && audio[0] == 0xFF && audio[1] & 0xE0 == 0xE0)
+    {
+        // An ID3 tag or an MPEG frame sync.
+        "mp3"
+    } else {
+        return None;
+    };
+    Some(extension)
+}
+
+/// MIME type for the file extension of a supported audio format.
+fn audio_mime(extension: &str) -> Option<&'static str> {
+    let mime = match extension.to_lowercase().as_str() {
+        "flac" => "audio/flac",
+        "m4a" | "mp4" => "audio/mp4",
+        "mp3" | "mpeg" | "mpga" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "wav" => "audio/wav",
+        "webm" => "audio/webm",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+const UNSUPPORTED_FORMAT: &str =
+    "unsupported audio format; use flac, m4a, mp3, mp4, ogg, wav, or webm";
+
+fn validate_size(audio: &[u8]) -> Result<(), Error> {
+    if MAX_AUDIO_BYTES < audio.len() {
+        return Err(Error::InvalidConfig(format!(
+            "audio is {} bytes, which is more than the maximum of {MAX_AUDIO_BYTES} bytes",
+            audio.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Check the size and format before uploading, so that the error is clear.
+fn validate_audio(audio: &[u8]) -> Result<&'static str, Error> {
+    validate_size(audio)?;
+    file_extension(audio).ok_or(Error::InvalidConfig(UNSUPPORTED_FORMAT.to_string()))
+}
+
+/// Multipart part for the audio.
+///
+/// The format is detected from the content unless the file name and MIME type
+/// are given.
+fn audio_part(audio: Bytes, file: Option<(String, &'static str)>) -> Result<Part, Error> {
+    match file {
+        Some((file_name, mime)) => {
+            validate_size(&audio)?;
+            Ok(Part::bytes(audio.to_vec())
+                .file_name(file_name)
+                .mime_str(mime)?)
+        }
+        None => {
+            let file_name = format!("audio.{}", validate_audio(&audio)?);
+            Ok(Part::bytes(audio.to_vec()).file_name(file_name))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Segment {
+    pub id: u64,
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Word {
+    pub word: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug)]
+pub struct Transcription {
+    pub text: String,
+    pub language: Option<String>,
+    pub segments: Option<Vec<Segment>>,
+    /// Word-level timestamps when "word" is in `timestamp_granularities`.
+    pub words: Option<Vec<Word>>,
+}
+
+pub struct TranscriptionResponse {
+    provider: Provider,
+    status: u16,
+    response_format: String,
+    resp: Bytes,
+}
+
+impl TranscriptionResponse {
+    pub fn bytes(&self) -> &Bytes {
+        &self.resp
+    }
+    pub fn raw_value(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_slice::<Value>(&self.resp)?)
+    }
+    pub fn structured(&self) -> Result<Transcription, Error> {
+        if self.response_format == "text" && (200..300).contains(&self.status) {
+            let text = String::from_utf8_lossy(&self.resp).trim().to_string();
+            return Ok(Transcription {
+                text,
+                language: None,
+                segments: None,
+                words: None,
+            });
+        }
+        let resp = self.raw_value()?;
+        tracing::debug!("Response: {resp}");
+        if let Some(error) = crate::api_error(&self.provider, self.status, &resp) {
+            return Err(error);
+        }
+        let text = match resp["text"].as_str() {
+            Some(text) => text.to_string(),
+            None => return Err(Error::Decode(format!("no text in response: {resp}"))),
+        };
+        let language = resp["language"].as_str().map(|s| s.to_string());
+        let segments = match resp.get("segments") {
+            Some(segments) => Some(serde_json::from_value(segments.clone())?),
+            None => None,
+        };
+        let words = match resp.get("words") {
+            Some(words) => Some(serde_json::from_value(words.clone())?),
+            None => None,
+        };
+        Ok(Transcription {
+            text,
+            language,
+            segments,
+            words,
+        })
+    }
+}
+
+/// Upload the audio as multipart/form-data to `/audio/{path}`.
+async fn upload(
+    key: &Key,
+    config: &STTConfig,
+    model: Option<&str>,
+    audio: Bytes,
+    file: Option<(String, &'static str)>,
+    path: &str,
+) -> Result<TranscriptionResponse, Error> {
+    let address = address(key, config, path)?;
+    let file = audio_part(audio, file)?;
+    let model = model.unwrap_or(default_model(&key.provider));
+    let response_format = config.response_format.clone().unwrap_or("json".to_string());
+    let mut form = Form::new()
+        .part("file", file)
+        .text("model", model.to_string())
+        .text("response_format", response_format.clone());
+    // Translations are always to English, so the language cannot be set.
+    if let (Some(language), "transcriptions") = (&config.language, path) {
+        form = form.text("language", language.clone());
+    }
+    if let Some(prompt) = &config.prompt {
+        form = form.text("prompt", prompt.clone());
+    }
+    for granularity in config.timestamp_granularities.iter().flatten() {
+        form = form.text("timestamp_granularities[]", granularity.clone());
+    }
+    // The multipart form sets its own content type including the boundary.
+    let mut headers = request_headers(key)?;
+    headers.remove("Content-Type");
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    tracing::debug!("Requesting {path} with model {model}");
+    let client = crate::client(&config.client)?;
+    let request = client.post(address).headers(headers).multipart(form);
+    let span = crate::request_span("stt", &key.provider, Some(model));
+    let resp = crate::send(request, None, config.timeout)
+        .instrument(span)
+        .await?;
+    let transcription_response = TranscriptionResponse {
+        provider: key.provider.clone(),
+        status: resp.status().into(),
+        response_format,
+        resp: resp.bytes().await?,
+    };
+    Ok(transcription_response)
+}
+
+/// Transcribe audio to text.
+///
+/// The audio is uploaded as multipart/form-data. Returns
+/// `Error::InvalidConfig` without sending the request when the audio is larger
+/// than `MAX_AUDIO_BYTES` or in an unsupported format.
+pub async fn transcribe(
+    key: &Key,
+    config: &STTConfig,
+    model: Option<&str>,
+    audio: Bytes,
+) -> Result<TranscriptionResponse, Error> {
+    upload(key, config, model, audio, None, "transcriptions").await
+}
+
+/// Transcribe an audio file.
+///
+/// The format is inferred from the file extension, such as "mp3" or "wav",
+/// and the file name is passed to the provider. Returns `Error::Io` when the
+/// file cannot be read and `Error::InvalidConfig` when the extension is not
+/// supported or the file is larger than `MAX_AUDIO_BYTES`.
+pub async fn transcribe_file(
+    key: &Key,
+    config: &STTConfig,
+    model: Option<&str>,
+    path: impl AsRef<Path>,
+) -> Result<TranscriptionResponse, Error> {
+    let path = path.as_ref();
+    let mime = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(audio_mime)
+        .ok_or(Error::InvalidConfig(UNSUPPORTED_FORMAT.to_string()))?;
+    let audio = Bytes::from(tokio::fs::read(path).await?);
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file = Some((file_name, mime));
+    upload(key, config, model, audio, file, "transcriptions").await
+}
+
+/// Transcribe a stream of audio segments, for example for live captioning.
+///
+/// Each item should be a complete audio file in a supported format, such as
+/// a few seconds of WAV from a microphone, since the segments are uploaded one
+/// by one. The transcription of each segment is yielded as soon as it is
+/// available. Unless `config.prompt` is set, the text of the previous segment
+/// is passed as prompt so that words at the boundaries are transcribed
+/// consistently.
+pub fn transcribe_stream<S>(
+    key: &Key,
+    config: &STTConfig,
+    model: Option<&str>,
+    audio: S,
+) -> Pin<Box<dyn Stream<Item = Result<Transcription, Error>> + Send>>
+where
+    S: Stream<Item = Bytes> + Send + 'static,
+{
+    let key = key.clone();
+    let config = config.clone();
+    let model = model.map(|model| model.to_string());
+    Box::pin(stream! {
+        let mut audio = Box::pin(audio);
+        let mut previous: Option<String> = None;
+        while let Some(segment) = audio.next().await {
+            let mut segment_config = config.clone();
+            if segment_config.prompt.is_none() {
+                segment_config.prompt = previous.clone();
+            }
+            let resp = upload(&key, &segment_config, model.as_deref(), segment, None, "transcriptions").await;
+            let transcription = resp.and_then(|resp| resp.structured());
+            if let Ok(transcription) = &transcription {
+                previous = Some(transcription.text.clone());
+            }
+            yield transcription;
+        }
+    })
+}
+
+/// Translate audio to English text.
+///
+/// Accepts the same audio formats and config as `transcribe`, except that
+/// `config.language` is ignored.
+pub async fn translate(
+    key: &Key,
+    config: &STTConfig,
+    model: Option<&str>,
+    audio: Bytes,
+) -> Result<TranscriptionResponse, Error> {
+    upload(key, config, model, audio, None, "translations").await
+}
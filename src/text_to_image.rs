@@ -3,15 +3,17 @@
 //! Functionality related to text-to-image.
 
 use crate::request_headers;
+use crate::Error;
 use crate::Key;
 use crate::Provider;
-use base64::prelude::*;
 use bytes::Bytes;
 use reqwest;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::Value;
-use std::error::Error;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::Instrument;
 
 /// Configuration for text-to-image.
 #[derive(Debug, Serialize, Deserialize)]
@@ -21,6 +23,15 @@ pub struct TTIConfig {
     pub cfg_scale: Option<u32>,
     pub height: Option<u32>,
     pub width: Option<u32>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
 }
 
 impl Default for TTIConfig {
@@ -31,15 +42,22 @@ impl Default for TTIConfig {
             cfg_scale: Some(3),
             height: Some(128),
             width: Some(128),
+            client: None,
+            timeout: None,
+            base_url: None,
+            extra_headers: None,
         }
     }
 }
 
-fn address(key: &Key, model: &str) -> String {
+fn address(key: &Key, config: &TTIConfig) -> Result<String, Error> {
+    let domain = crate::domain(&key.provider, config.base_url.as_deref());
+    let model = &config.model;
     match key.provider {
-        Provider::Hyperbolic => format!("{}/v1/image/generation", key.provider.domain()),
-        Provider::DeepInfra => format!("{}/v1/inference/{}", key.provider.domain(), model),
-        _ => format!("{}/v1/image/generation", key.provider.domain()),
+        // Self-hosted servers are assumed to be Hyperbolic-compatible.
+        Provider::Hyperbolic | Provider::Other(_) => Ok(format!("{domain}/v1/image/generation")),
+        Provider::DeepInfra => Ok(format!("{domain}/v1/inference/{model}")),
+        _ => Err(Error::UnsupportedProvider(key.provider.clone())),
     }
 }
 
@@ -56,18 +74,21 @@ pub struct Image {
 }
 
 impl Base64Image {
-    pub fn base64_decode(&self) -> Result<Image, Box<dyn Error + Send + Sync>> {
+    pub fn base64_decode(&self) -> Result<Image, Error> {
         let re = regex::Regex::new(r"^data:image/(\w+);base64,").unwrap();
         let filetype = match re
             .captures(&self.image)
-            .map(|cap| cap.get(1).unwrap().as_str())
+            .and_then(|cap| Some(cap.get(1)?.as_str()))
         {
             Some("png") => "png",
             Some("jpg" | "jpeg") => "jpg",
             _ => "unknown",
         };
         let image = re.replace(&self.image, "").to_string();
-        let bytes = BASE64_STANDARD.decode(image.as_bytes()).expect("no decode");
+        let bytes = match crate::base64_decode(&image) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(Error::Decode(format!("invalid base64 image: {e}"))),
+        };
         Ok(Image {
             filetype: filetype.to_string(),
             image: Bytes::from(bytes),
@@ -82,6 +103,7 @@ pub struct Images {
 
 pub struct ImageResponse {
     provider: Provider,
+    status: u16,
     resp: Bytes,
 }
 
@@ -89,28 +111,31 @@ impl ImageResponse {
     pub fn bytes(&self) -> &Bytes {
         &self.resp
     }
-    pub fn raw_value(&self) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    pub fn raw_value(&self) -> Result<Value, Error> {
         Ok(serde_json::from_slice::<Value>(&self.resp)?)
     }
-    pub fn structured(&self) -> Result<Images, Box<dyn Error + Send + Sync>> {
+    pub fn structured(&self) -> Result<Images, Error> {
         let resp = self.raw_value()?;
         tracing::debug!("Response: {resp}");
         let resp: Images = if self.provider == Provider::DeepInfra {
-            if resp.get("detail").is_some() {
-                return Err(format!("DeepInfra returned an error: {}", resp["detail"]).into());
+            if let Some(error) = crate::api_error(&self.provider, self.status, &resp) {
+                return Err(error);
             }
-            let image = resp["images"][0].clone();
+            let image = match resp["images"][0].as_str() {
+                Some(image) => image.to_string(),
+                None => return Err(Error::Decode(format!("no image in response:\n{resp}"))),
+            };
             let images: Vec<Base64Image> = vec![Base64Image {
                 index: 0,
                 random_seed: None,
-                image: image.as_str().unwrap().to_string(),
+                image,
             }];
             Images { images }
         } else {
             match serde_json::from_value(resp.clone()) {
                 Ok(json) => json,
                 Err(e) => {
-                    return Err(format!("{e} in response:\n{}", resp).into());
+                    return Err(Error::Decode(format!("{e} in response:\n{}", resp)));
                 }
             }
         };
@@ -122,8 +147,8 @@ pub async fn text_to_image(
     key: &Key,
     config: TTIConfig,
     prompt: &str,
-) -> Result<ImageResponse, Box<dyn Error + Send + Sync>> {
-    let address = address(key, &config.model);
+) -> Result<ImageResponse, Error> {
+    let address = address(key, &config)?;
     let mut body = serde_json::json!({
         "model_name": config.model,
         "prompt": prompt,
@@ -141,15 +166,17 @@ pub async fn text_to_image(
         body["width"] = serde_json::Value::from(width);
     }
     tracing::debug!("Requesting image: {body}");
-    let client = reqwest::Client::new();
-    let resp = client
-        .post(address)
-        .headers(request_headers(key)?)
-        .json(&body)
-        .send()
+    let mut headers = request_headers(key)?;
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    let client = crate::client(&config.client)?;
+    let request = client.post(address).headers(headers).json(&body);
+    let span = crate::request_span("image_generation", &key.provider, Some(&config.model));
+    let resp = crate::send(request, None, config.timeout)
+        .instrument(span)
         .await?;
     let image_response = ImageResponse {
         provider: key.provider.clone(),
+        status: resp.status().into(),
         resp: resp.bytes().await?,
     };
     Ok(image_response)
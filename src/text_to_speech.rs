@@ -3,43 +3,459 @@
 //! Functionality related to text-to-speech.
 
 use crate::request_headers;
+use crate::AuthScheme;
+use crate::Error;
 use crate::Key;
 use crate::Provider;
-use base64::prelude::*;
+use crate::RateLimitInfo;
+use crate::RequestParts;
+use crate::RetryConfig;
+use async_stream::stream;
 use bytes::Bytes;
+use futures::Stream;
+use futures::StreamExt;
 use reqwest;
+use reqwest::header::HeaderMap;
+use reqwest::Response;
+use reqwest::Url;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::json;
 use serde_json::Value;
 use std::collections::HashMap;
-use std::error::Error;
+use std::future::Future;
+use std::path::Path;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::LazyLock;
+use std::time::Duration;
+use std::time::SystemTime;
+use tracing::Instrument;
+
+/// Format of the text that is passed to `tts`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum InputType {
+    #[default]
+    Text,
+    /// Speech Synthesis Markup Language, for example "<speak>Hello</speak>".
+    ///
+    /// Only supported by Amazon and Google.
+    Ssml,
+}
 
 /// Text-to-speech config
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TTSConfig {
+    /// For example, "mp3" or "wav".
+    ///
+    /// Providers return one format per request, see also `tts_formats`.
     pub output_format: Option<String>,
     pub voice: Option<String>,
+    /// Speaking rate, where 1.0 is the normal speed.
+    ///
+    /// Returns `Error::InvalidConfig` when it is outside the range of the
+    /// provider, unless `clamp_speed` is set.
     pub speed: Option<f32>,
+    /// Clamp `speed` to the range of the provider instead of returning an
+    /// error.
+    #[serde(default)]
+    pub clamp_speed: bool,
+    /// Pitch in semitones, for example -2.0 or 2.0.
+    ///
+    /// Only supported by Google.
+    pub pitch: Option<f32>,
+    /// Volume gain in dB, for example -6.0 for half the amplitude.
+    ///
+    /// Only supported by Google.
+    pub volume_gain_db: Option<f32>,
+    /// Language of the voice, for example "en-US".
+    ///
+    /// For Google, it is derived from the voice when `None`, for example
+    /// "en-US" for "en-US-Studio-Q".
     pub language_code: Option<String>,
+    #[serde(default)]
+    pub input_type: InputType,
     pub other: Option<HashMap<String, Value>>,
+    /// See [common options](crate#common-options).
+    pub retry: Option<RetryConfig>,
+    /// See [common options](crate#common-options).
+    #[serde(skip)]
+    pub client: Option<reqwest::Client>,
+    /// See [common options](crate#common-options).
+    pub timeout: Option<Duration>,
+    /// See [common options](crate#common-options).
+    pub base_url: Option<String>,
+    /// See [common options](crate#common-options).
+    pub extra_headers: Option<HashMap<String, String>>,
+    /// Maximum number of characters per request in `tts_long`.
+    ///
+    /// Defaults to `DEFAULT_MAX_CHARS` when `None`.
+    pub max_chars: Option<usize>,
+}
+
+impl TTSConfig {
+    /// Builder for the config, for example
+    /// `TTSConfig::builder().voice("alloy").speed(1.2).build()`.
+    pub fn builder() -> TTSConfigBuilder {
+        TTSConfigBuilder::default()
+    }
+}
+
+/// Builder for `TTSConfig`.
+///
+/// Fields that are not set keep their default.
+#[derive(Clone, Debug, Default)]
+pub struct TTSConfigBuilder {
+    config: TTSConfig,
+}
+
+impl TTSConfigBuilder {
+    pub fn output_format(mut self, output_format: &str) -> Self {
+        self.config.output_format = Some(output_format.to_string());
+        self
+    }
+    pub fn voice(mut self, voice: &str) -> Self {
+        self.config.voice = Some(voice.to_string());
+        self
+    }
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.config.speed = Some(speed);
+        self
+    }
+    pub fn clamp_speed(mut self, clamp_speed: bool) -> Self {
+        self.config.clamp_speed = clamp_speed;
+        self
+    }
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.config.pitch = Some(pitch);
+        self
+    }
+    pub fn volume_gain_db(mut self, volume_gain_db: f32) -> Self {
+        self.config.volume_gain_db = Some(volume_gain_db);
+        self
+    }
+    pub fn language_code(mut self, language_code: &str) -> Self {
+        self.config.language_code = Some(language_code.to_string());
+        self
+    }
+    pub fn input_type(mut self, input_type: InputType) -> Self {
+        self.config.input_type = input_type;
+        self
+    }
+    /// Add a provider-specific field to the request body.
+    pub fn other(mut self, key: &str, value: Value) -> Self {
+        self.config
+            .other
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value);
+        self
+    }
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.config.retry = Some(retry);
+        self
+    }
+    pub fn client(mut self, client: reqwest::Client) -> Self {
+        self.config.client = Some(client);
+        self
+    }
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.config.base_url = Some(base_url.to_string());
+        self
+    }
+    pub fn max_chars(mut self, max_chars: usize) -> Self {
+        self.config.max_chars = Some(max_chars);
+        self
+    }
+    /// Add a header to the request.
+    pub fn extra_header(mut self, name: &str, value: &str) -> Self {
+        self.config
+            .extra_headers
+            .get_or_insert_with(HashMap::new)
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+    pub fn build(self) -> TTSConfig {
+        self.config
+    }
 }
 
-fn address(key: &Key, model: Option<&str>) -> String {
-    if key.provider == Provider::DeepInfra {
-        let model = model.unwrap_or("hexgrad/Kokoro-82M");
-        format!("{}/v1/inference/{}", key.provider.domain(), model)
+/// Default maximum number of characters per request in `tts_long`.
+///
+/// This is the limit of the OpenAI speech endpoint.
+pub const DEFAULT_MAX_CHARS: usize = 4096;
+
+/// Whether the provider uses the OpenAI speech API, which is also used by Azure
+/// and Together AI.
+fn is_openai(provider: &Provider) -> bool {
+    matches!(
+        provider,
+        Provider::Azure | Provider::OpenAI | Provider::TogetherAI
+    )
+}
+
+/// Whether the provider responds with the audio itself instead of base64 in
+/// JSON.
+fn returns_binary(provider: &Provider) -> bool {
+    is_openai(provider) || matches!(provider, Provider::Amazon | Provider::ElevenLabs)
+}
+
+/// File format for an audio `Content-Type`, for example "mp3" for "audio/mpeg".
+fn mime_format(content_type: &str) -> Option<&'static str> {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    match mime.to_lowercase().as_str() {
+        "audio/aac" => Some("aac"),
+        "audio/flac" | "audio/x-flac" => Some("flac"),
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/ogg" => Some("ogg"),
+        "audio/opus" => Some("opus"),
+        "audio/pcm" | "audio/l16" => Some("pcm"),
+        "audio/wav" | "audio/wave" | "audio/x-wav" => Some("wav"),
+        _ => None,
+    }
+}
+
+/// Default ElevenLabs voice ("Rachel"), which is used when no voice is set.
+const ELEVENLABS_VOICE: &str = "21m00Tcm4TlvDq8ikWAM";
+
+fn address(key: &Key, config: &TTSConfig, model: Option<&str>) -> Result<String, Error> {
+    let domain = crate::domain(&key.provider, config.base_url.as_deref());
+    let address = if key.provider == Provider::DeepInfra {
+        let model = match model.or(key.provider.default_tts_model()) {
+            Some(model) => model,
+            None => {
+                return Err(Error::InvalidConfig(format!(
+                    "no model was passed and {} has no default model",
+                    key.provider
+                )))
+            }
+        };
+        format!("{domain}/v1/inference/{model}")
     } else if key.provider == Provider::Hyperbolic {
-        format!("{}/v1/audio/generation", key.provider.domain())
-    } else if key.provider == Provider::OpenAI {
-        format!("{}/v1/audio/speech", key.provider.domain())
+        format!("{domain}/v1/audio/generation")
+    } else if key.provider == Provider::OpenAI || key.provider == Provider::TogetherAI {
+        format!("{domain}/v1/audio/speech")
+    } else if key.provider == Provider::Azure {
+        let deployment = match model {
+            Some(model) => model,
+            None => {
+                return Err(Error::InvalidConfig(
+                    "Azure requires the deployment to be passed as model".to_string(),
+                ))
+            }
+        };
+        crate::azure_address(key, config.base_url.as_deref(), deployment, "audio/speech")?
     } else if key.provider == Provider::Google {
-        let domain = "https://texttospeech.googleapis.com";
+        let domain = match &config.base_url {
+            Some(base_url) => base_url.trim_end_matches('/'),
+            None => "https://texttospeech.googleapis.com",
+        };
         let path = "/v1beta1/text:synthesize";
         format!("{domain}{path}?key={}", key.key)
+    } else if key.provider == Provider::Amazon {
+        let domain = match &config.base_url {
+            Some(base_url) => base_url.trim_end_matches('/'),
+            None => crate::amazon::DEFAULT_DOMAIN,
+        };
+        format!("{domain}/v1/speech")
+    } else if key.provider == Provider::ElevenLabs {
+        let voice = config.voice.as_deref().unwrap_or(ELEVENLABS_VOICE);
+        let mut address = format!("{domain}/v1/text-to-speech/{voice}");
+        if let Some(output_format) = &config.output_format {
+            address = format!("{address}?output_format={output_format}");
+        }
+        address
     } else {
-        panic!("Unsupported TTS provider: {}", key.provider);
+        return Err(Error::UnsupportedProvider(key.provider.clone()));
+    };
+    Ok(address)
+}
+
+/// Voice of a text to speech provider.
+///
+/// Compiled-in catalogs are available via `openai_voices`, `deepinfra_voices`,
+/// and `hyperbolic_voices`. Google voices are only available via `list_voices`,
+/// since Google offers hundreds of voices that change over time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Voice {
+    /// Identifier to pass as `TTSConfig.voice`.
+    pub id: String,
+    pub name: String,
+    pub language_codes: Vec<String>,
+    pub gender: Option<String>,
+}
+
+/// Voices of the OpenAI speech API, which are also available on Azure.
+///
+/// The list is compiled in, since OpenAI has a fixed set of voices and no
+/// voices endpoint.
+pub fn openai_voices() -> &'static [Voice] {
+    static VOICES: LazyLock<Vec<Voice>> = LazyLock::new(|| {
+        let ids = [
+            "alloy", "ash", "coral", "echo", "fable", "onyx", "nova", "sage", "shimmer",
+        ];
+        ids.iter()
+            .map(|id| Voice {
+                id: id.to_string(),
+                name: id[..1].to_uppercase() + &id[1..],
+                language_codes: vec!["en-US".to_string()],
+                gender: None,
+            })
+            .collect()
+    });
+    &VOICES
+}
+
+/// Voices for the default DeepInfra model `hexgrad/Kokoro-82M`.
+///
+/// The first letter of the id denotes the accent and the second the gender.
+pub fn deepinfra_voices() -> &'static [Voice] {
+    static VOICES: LazyLock<Vec<Voice>> = LazyLock::new(|| {
+        let ids = [
+            "af_alloy",
+            "af_bella",
+            "af_heart",
+            "af_nicole",
+            "af_nova",
+            "af_sarah",
+            "af_sky",
+            "am_adam",
+            "am_echo",
+            "am_eric",
+            "am_liam",
+            "am_michael",
+            "am_onyx",
+            "bf_alice",
+            "bf_emma",
+            "bf_isabella",
+            "bf_lily",
+            "bm_daniel",
+            "bm_fable",
+            "bm_george",
+            "bm_lewis",
+        ];
+        ids.iter()
+            .map(|id| {
+                let language_code = if id.starts_with('b') {
+                    "en-GB"
+                } else {
+                    "en-US"
+                };
+                let gender = if id.chars().nth(1) == Some('f') {
+                    "female"
+                } else {
+                    "male"
+                };
+                let name = &id[3..];
+                Voice {
+                    id: id.to_string(),
+                    name: name[..1].to_uppercase() + &name[1..],
+                    language_codes: vec![language_code.to_string()],
+                    gender: Some(gender.to_string()),
+                }
+            })
+            .collect()
+    });
+    &VOICES
+}
+
+/// Speakers of the MeloTTS model behind the Hyperbolic endpoint.
+///
+/// The language is derived from the part before the dash, for example "EN"
+/// for "EN-US".
+pub fn hyperbolic_voices() -> &'static [Voice] {
+    static VOICES: LazyLock<Vec<Voice>> = LazyLock::new(|| {
+        let voices = [
+            ("EN-US", "American English", "en-US"),
+            ("EN-BR", "British English", "en-GB"),
+            ("EN-INDIA", "Indian English", "en-IN"),
+            ("EN-AU", "Australian English", "en-AU"),
+            ("EN-Default", "English", "en"),
+            ("ES", "Spanish", "es"),
+            ("FR", "French", "fr"),
+            ("ZH", "Chinese", "zh"),
+            ("JP", "Japanese", "ja"),
+            ("KR", "Korean", "ko"),
+        ];
+        voices
+            .iter()
+            .map(|(id, name, language_code)| Voice {
+                id: id.to_string(),
+                name: name.to_string(),
+                language_codes: vec![language_code.to_string()],
+                gender: None,
+            })
+            .collect()
+    });
+    &VOICES
+}
+
+/// Request the voices from the Google voices endpoint.
+async fn fetch_google_voices(key: &Key) -> Result<Vec<Voice>, Error> {
+    let address = format!(
+        "https://texttospeech.googleapis.com/v1/voices?key={}",
+        key.key
+    );
+    let headers = crate::request_headers_with_auth(key, AuthScheme::Query)?;
+    let client = crate::client_builder().build()?;
+    let request = client.get(address).headers(headers);
+    let span = crate::request_span("voices", &key.provider, None);
+    let resp = crate::send(request, None, None).instrument(span).await?;
+    let status = resp.status().as_u16();
+    let resp = resp.json::<Value>().await?;
+    if let Some(error) = crate::api_error(&key.provider, status, &resp) {
+        return Err(error);
     }
+    let voices = match resp["voices"].as_array() {
+        Some(voices) => voices,
+        None => return Err(Error::Decode(format!("no voices in response: {resp}"))),
+    };
+    let voices = voices
+        .iter()
+        .filter_map(|voice| {
+            let id = voice["name"].as_str()?.to_string();
+            let language_codes = voice["languageCodes"]
+                .as_array()?
+                .iter()
+                .filter_map(|code| code.as_str().map(|code| code.to_string()))
+                .collect();
+            let gender = voice["ssmlGender"].as_str().map(|g| g.to_lowercase());
+            Some(Voice {
+                name: id.clone(),
+                id,
+                language_codes,
+                gender,
+            })
+        })
+        .collect();
+    Ok(voices)
+}
+
+/// List the available voices for the provider.
+///
+/// For Google, the voices are requested from the API. For providers without a
+/// voices endpoint, the compiled-in list is returned, which is also available
+/// without a key via `openai_voices`, `deepinfra_voices`, and
+/// `hyperbolic_voices`.
+pub async fn list_voices(key: &Key) -> Result<Vec<Voice>, Error> {
+    match key.provider {
+        Provider::DeepInfra => Ok(deepinfra_voices().to_vec()),
+        Provider::Google => fetch_google_voices(key).await,
+        Provider::Hyperbolic => Ok(hyperbolic_voices().to_vec()),
+        Provider::Azure | Provider::OpenAI => Ok(openai_voices().to_vec()),
+        _ => Err(Error::UnsupportedProvider(key.provider.clone())),
+    }
+}
+
+/// Time at which a `<mark>` in the SSML input is reached in the audio.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timepoint {
+    pub mark_name: String,
+    pub time_seconds: f64,
 }
 
 #[derive(Debug)]
@@ -47,167 +463,845 @@ pub struct Speech {
     pub request_id: Option<String>,
     pub file_format: String,
     pub audio: Bytes,
+    /// Timepoints of the marks in the input.
+    ///
+    /// Only returned by Google and only when `enableTimePointing` is set to
+    /// `["SSML_MARK"]` via `TTSConfig.other`. Empty for other providers.
+    pub timepoints: Vec<Timepoint>,
+    /// Sample rate in Hz.
+    ///
+    /// Read from the header for WAV audio and from the output format for raw
+    /// PCM, such as "pcm_16000" for ElevenLabs. `None` when it cannot be
+    /// derived, for example for mp3.
+    pub sample_rate: Option<u32>,
+    /// Duration of the audio in seconds.
+    ///
+    /// Only known for WAV and raw PCM audio.
+    pub duration_seconds: Option<f64>,
+}
+
+/// Split a data URI such as "data:audio/wav;base64,..." into format and payload.
+fn split_data_uri(audio: &str) -> Option<(String, &str)> {
+    let (format, payload) = audio.strip_prefix("data:audio/")?.split_once(";base64,")?;
+    let is_format = |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '+' | '-');
+    if format.is_empty() || !format.chars().all(is_format) {
+        return None;
+    }
+    let format = match format {
+        "mpeg" => "mp3",
+        "x-wav" | "wave" => "wav",
+        format => format,
+    };
+    Some((format.to_string(), payload))
 }
 
 impl Speech {
     /// Convert the base64 encoded audio to bytes.
     ///
+    /// The audio may be prefixed by a data URI such as
+    /// "data:audio/mp3;base64,". DeepInfra always returns such a prefix.
+    ///
     /// These bytes can then, for example, be written to a file.
-    pub fn base64_decode(
-        audio: &str,
-        provider: &Provider,
-    ) -> Result<Bytes, Box<dyn Error + Send + Sync>> {
-        let stripped = if provider == &Provider::DeepInfra {
-            let deepinfra_prefix = "data:audio/mp3;base64,";
-            audio.strip_prefix(deepinfra_prefix).expect("no mp3 prefix")
-        } else {
-            audio
+    pub fn base64_decode(audio: &str, provider: &Provider) -> Result<Bytes, Error> {
+        let stripped = match split_data_uri(audio) {
+            Some((_format, payload)) => payload,
+            None if provider == &Provider::DeepInfra => {
+                return Err(Error::Decode(
+                    "expected audio to start with a data URI such as data:audio/mp3;base64,"
+                        .to_string(),
+                ))
+            }
+            None => audio,
         };
-        let bytes = BASE64_STANDARD.decode(stripped).expect("no decode");
-        Ok(Bytes::from(bytes))
+        match crate::base64_decode(stripped) {
+            Ok(bytes) => Ok(Bytes::from(bytes)),
+            Err(e) => Err(Error::Decode(format!("invalid base64 audio: {e}"))),
+        }
+    }
+    /// Set `sample_rate` and `duration_seconds` from the WAV header or, for
+    /// raw 16-bit mono PCM, from `pcm_sample_rate`.
+    fn with_audio_info(mut self, pcm_sample_rate: Option<u32>) -> Self {
+        if let Some((sample_rate, duration_seconds)) = wav_info(&self.audio) {
+            self.sample_rate = Some(sample_rate);
+            self.duration_seconds = Some(duration_seconds);
+        } else if let Some(sample_rate) = pcm_sample_rate {
+            self.sample_rate = Some(sample_rate);
+            self.duration_seconds = Some(self.audio.len() as f64 / (2.0 * sample_rate as f64));
+        }
+        self
+    }
+    /// Audio as WAV, for example to make raw 16-bit PCM playable.
+    ///
+    /// Audio that already has a WAV header is returned unchanged.
+    pub fn to_wav(&self, sample_rate: u32, channels: u16) -> Bytes {
+        if self.audio.starts_with(b"RIFF") {
+            return self.audio.clone();
+        }
+        let bits_per_sample: u16 = 16;
+        let block_align = channels * bits_per_sample / 8;
+        let byte_rate = sample_rate * block_align as u32;
+        let mut fmt = Vec::with_capacity(16);
+        // Audio format 1 is PCM.
+        fmt.extend_from_slice(&1u16.to_le_bytes());
+        fmt.extend_from_slice(&channels.to_le_bytes());
+        fmt.extend_from_slice(&sample_rate.to_le_bytes());
+        fmt.extend_from_slice(&byte_rate.to_le_bytes());
+        fmt.extend_from_slice(&block_align.to_le_bytes());
+        fmt.extend_from_slice(&bits_per_sample.to_le_bytes());
+        wav(&fmt, &self.audio)
+    }
+    /// Write the audio to a file at `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, &self.audio)
+    }
+    /// Write the audio to `{dir}/{stem}.{file_format}`.
+    ///
+    /// Returns the path of the written file.
+    pub fn save_with_extension(
+        &self,
+        dir: impl AsRef<Path>,
+        stem: &str,
+    ) -> std::io::Result<PathBuf> {
+        let path = dir.as_ref().join(format!("{stem}.{}", self.file_format));
+        self.save(&path)?;
+        Ok(path)
     }
 }
 
 pub struct SpeechResponse {
-    provider: Provider,
-    resp: Bytes,
+    pub(crate) provider: Provider,
+    pub(crate) status: u16,
+    /// The requested output format.
+    pub(crate) output_format: Option<String>,
+    pub(crate) rate_limit: Option<RateLimitInfo>,
+    /// The `Content-Type` header of the response.
+    pub(crate) content_type: Option<String>,
+    pub(crate) resp: Bytes,
 }
 
 impl SpeechResponse {
+    /// Rate limits from the response headers, if the provider sent them.
+    pub fn rate_limit(&self) -> Option<&RateLimitInfo> {
+        self.rate_limit.as_ref()
+    }
     pub fn bytes(&self) -> &Bytes {
         &self.resp
     }
-    pub fn raw_value(&self) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    pub fn raw_value(&self) -> Result<Value, Error> {
         Ok(serde_json::from_slice::<Value>(&self.resp)?)
     }
-    pub fn structured(&self) -> Result<Speech, Box<dyn Error + Send + Sync>> {
-        if self.provider == Provider::DeepInfra {
-            let resp = self.raw_value()?;
-            tracing::debug!("Response: {resp}");
-            if resp.get("detail").is_some() {
-                return Err(format!("DeepInfra returned an error: {}", resp["detail"]).into());
+    /// Sample rate of raw PCM audio, which has no header.
+    fn pcm_sample_rate(&self) -> Option<u32> {
+        let output_format = self.output_format.as_deref()?;
+        if self.provider == Provider::ElevenLabs {
+            // For example, "pcm_16000".
+            let rate = output_format.strip_prefix("pcm_")?;
+            return rate.parse().ok();
+        }
+        if is_openai(&self.provider) && output_format == "pcm" {
+            // OpenAI returns 24 kHz PCM.
+            return Some(24000);
+        }
+        None
+    }
+    /// Parse the response into a `Speech`.
+    ///
+    /// Returns `Error::Api` with the body text, or the `detail` field when
+    /// present, when the status is not 2xx.
+    ///
+    /// Returns `Error::PartialAudio` with the body when the base64 audio could
+    /// not be decoded, for example because the payload was truncated.
+    pub fn structured(&self) -> Result<Speech, Error> {
+        let speech = self.speech()?;
+        Ok(speech.with_audio_info(self.pcm_sample_rate()))
+    }
+    /// Decode the base64 audio in the response body.
+    fn decode_audio(&self, audio: &str) -> Result<Bytes, Error> {
+        Speech::base64_decode(audio, &self.provider).map_err(|e| Error::PartialAudio {
+            bytes: self.resp.clone(),
+            source: Box::new(e),
+        })
+    }
+    fn speech(&self) -> Result<Speech, Error> {
+        if !(200..300).contains(&self.status) {
+            let error = self
+                .raw_value()
+                .ok()
+                .and_then(|resp| crate::api_error(&self.provider, self.status, &resp));
+            return Err(error.unwrap_or_else(|| Error::Api {
+                provider: self.provider.clone(),
+                status: self.status,
+                message: String::from_utf8_lossy(&self.resp).to_string(),
+            }));
+        }
+        let content_type = self.content_type.as_deref().unwrap_or_default();
+        if content_type.starts_with("audio/") || content_type == "application/octet-stream" {
+            return self.binary_speech();
+        }
+        match serde_json::from_slice::<Value>(&self.resp) {
+            Ok(resp) => self.json_speech(&resp),
+            // Without an audio content type, the body is not always JSON.
+            Err(_) if returns_binary(&self.provider) => self.binary_speech(),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /// Speech from a response with base64 audio in JSON.
+    fn json_speech(&self, resp: &Value) -> Result<Speech, Error> {
+        tracing::debug!("Response: {resp}");
+        if let Some(error) = crate::api_error(&self.provider, self.status, resp) {
+            return Err(error);
+        }
+        // Google uses `audioContent` while the others use `audio`.
+        let audio = match resp["audio"].as_str().or(resp["audioContent"].as_str()) {
+            Some(audio) => audio,
+            None if returns_binary(&self.provider) => {
+                return Err(Error::Decode(format!(
+                    "expected audio but received JSON: {resp}"
+                )))
             }
-            let audio = resp["audio"].as_str().expect("no audio in resp");
-            let out = Speech {
-                request_id: Some(resp["request_id"].as_str().unwrap().to_string()),
-                file_format: resp["output_format"].as_str().unwrap().to_string(),
-                audio: Speech::base64_decode(audio, &self.provider)?,
-            };
-            Ok(out)
-        } else if self.provider == Provider::Hyperbolic {
-            let resp = self.raw_value()?;
-            tracing::debug!("Response: {resp}");
-            let audio = &resp["audio"].as_str().unwrap();
-            let out = Speech {
-                request_id: None,
-                file_format: "mp3".to_string(),
-                audio: Speech::base64_decode(audio, &self.provider)?,
-            };
-            Ok(out)
-        } else if self.provider == Provider::OpenAI {
-            let audio = self.resp.clone();
-            if let Ok(resp) = serde_json::from_slice::<Value>(&self.resp) {
-                tracing::debug!("Response: {resp}");
-                if resp.get("error").is_some() {
-                    return Err(resp["error"].to_string().into());
-                }
+            None => return Err(Error::Decode(format!("no audio in response: {resp}"))),
+        };
+        let file_format = match (split_data_uri(audio), resp["output_format"].as_str()) {
+            (Some((format, _payload)), _) => format,
+            (None, Some(output_format)) => output_format.to_string(),
+            (None, None) => self.file_format()?,
+        };
+        let timepoints = match resp.get("timepoints") {
+            Some(timepoints) => serde_json::from_value(timepoints.clone())?,
+            None => vec![],
+        };
+        let mut out = Speech {
+            request_id: resp["request_id"].as_str().map(|id| id.to_string()),
+            file_format,
+            audio: self.decode_audio(audio)?,
+            timepoints,
+            sample_rate: None,
+            duration_seconds: None,
+        };
+        if self.provider == Provider::Google && out.file_format == "wav" {
+            // LINEAR16 usually contains a WAV header, but add one if not so
+            // that the file can be played.
+            out.audio = out.to_wav(GOOGLE_SAMPLE_RATE, 1);
+        }
+        Ok(out)
+    }
+    /// Speech from a response whose body is the audio itself.
+    fn binary_speech(&self) -> Result<Speech, Error> {
+        Ok(Speech {
+            request_id: None,
+            file_format: self.file_format()?,
+            audio: self.resp.clone(),
+            timepoints: vec![],
+            sample_rate: None,
+            duration_seconds: None,
+        })
+    }
+    /// File format of the audio based on the requested output format.
+    fn file_format(&self) -> Result<String, Error> {
+        let file_format = match (&self.provider, &self.output_format) {
+            // For example, "mp3_44100_128" or "pcm_16000".
+            (Provider::ElevenLabs, Some(output_format)) => {
+                output_format.split('_').next().unwrap().to_string()
             }
-            let out = Speech {
-                request_id: None,
-                file_format: "mp3".to_string(),
-                audio,
-            };
-            Ok(out)
-        } else if self.provider == Provider::Google {
-            let resp = self.raw_value()?;
-            tracing::debug!("Response: {resp}");
-            if resp.get("error").is_some() {
-                return Err(resp["error"].to_string().into());
+            (Provider::Amazon, output_format) => {
+                let (_, file_format) = crate::amazon::output_format(output_format.as_deref())?;
+                file_format.to_string()
             }
-            let audio = &resp["audioContent"].as_str().expect("audioContent");
-            let _timepoints = &resp["timepoints"].as_array().unwrap();
-            let out = Speech {
-                request_id: None,
-                file_format: "mp3".to_string(),
-                audio: Speech::base64_decode(audio, &self.provider)?,
-            };
-            Ok(out)
-        } else {
-            panic!("Unsupported TTS provider: {}", self.provider);
-        }
+            (Provider::Google, output_format) => {
+                let (_, file_format) = google_encoding(output_format.as_deref())?;
+                file_format.to_string()
+            }
+            (_, Some(output_format)) => output_format.clone(),
+            (provider, None) => {
+                let content_type = self.content_type.as_deref().unwrap_or_default();
+                match mime_format(content_type) {
+                    Some(file_format) => file_format.to_string(),
+                    // Together AI defaults to WAV while the others default to mp3.
+                    None if *provider == Provider::TogetherAI => "wav".to_string(),
+                    None => "mp3".to_string(),
+                }
+            }
+        };
+        Ok(file_format)
     }
 }
 
-pub async fn tts(
+/// Valid range of `speed` for the provider, if known.
+fn speed_range(provider: &Provider) -> Option<(f32, f32)> {
+    match provider {
+        Provider::Azure | Provider::Google | Provider::OpenAI => Some((0.25, 4.0)),
+        Provider::ElevenLabs => Some((0.7, 1.2)),
+        _ => None,
+    }
+}
+
+/// Speed after validating or clamping it to the range of the provider.
+fn speed(provider: &Provider, config: &TTSConfig) -> Result<Option<f32>, Error> {
+    let (speed, (min, max)) = match (config.speed, speed_range(provider)) {
+        (Some(speed), Some(range)) => (speed, range),
+        (speed, _) => return Ok(speed),
+    };
+    if config.clamp_speed {
+        Ok(Some(speed.clamp(min, max)))
+    } else if (min..=max).contains(&speed) {
+        Ok(Some(speed))
+    } else {
+        Err(Error::InvalidConfig(format!(
+            "speed {speed} is outside the range {min} to {max} of {provider}"
+        )))
+    }
+}
+
+/// Sample rate of Google's LINEAR16 audio when `sampleRateHertz` is not set.
+const GOOGLE_SAMPLE_RATE: u32 = 24000;
+
+/// Map the output format to Google's `audioEncoding` and the resulting file
+/// format.
+///
+/// Google returns WAV (`LINEAR16`) when no output format is set.
+fn google_encoding(output_format: Option<&str>) -> Result<(&'static str, &'static str), Error> {
+    match output_format {
+        None | Some("wav") | Some("linear16") => Ok(("LINEAR16", "wav")),
+        Some("mp3") => Ok(("MP3", "mp3")),
+        Some("ogg") | Some("opus") => Ok(("OGG_OPUS", "ogg")),
+        Some(output_format) => Err(Error::InvalidConfig(format!(
+            "output format {output_format} is not supported by Google"
+        ))),
+    }
+}
+
+/// Language code at the start of a voice such as "en-US-Studio-Q" or
+/// "cmn-CN-Wavenet-A".
+fn voice_language_code(voice: &str) -> Option<&str> {
+    let mut parts = voice.splitn(3, '-');
+    let language = parts.next()?;
+    let region = parts.next()?;
+    let is_language =
+        (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_lowercase());
+    let is_region = region.len() == 2 && region.chars().all(|c| c.is_ascii_uppercase());
+    if is_language && is_region && parts.next().is_some() {
+        Some(&voice[..language.len() + 1 + region.len()])
+    } else {
+        None
+    }
+}
+
+pub(crate) fn request_parts(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<RequestParts, Error> {
+    request_parts_at(key, config, model, text, SystemTime::now())
+}
+
+/// Like `request_parts`, where Amazon requests are signed for `time`.
+fn request_parts_at(
     key: &Key,
     config: &TTSConfig,
     model: Option<&str>,
     text: &str,
-) -> Result<SpeechResponse, Box<dyn Error + Send + Sync>> {
-    let address = address(key, model);
+    time: SystemTime,
+) -> Result<RequestParts, Error> {
+    let address = address(key, config, model)?;
+    let supports_ssml = matches!(key.provider, Provider::Amazon | Provider::Google);
+    if config.input_type == InputType::Ssml && !supports_ssml {
+        return Err(Error::InvalidConfig(format!(
+            "SSML input is not supported by {}",
+            key.provider
+        )));
+    }
+    let has_audio_config = config.pitch.is_some() || config.volume_gain_db.is_some();
+    if has_audio_config && key.provider != Provider::Google {
+        return Err(Error::InvalidConfig(format!(
+            "pitch and volume_gain_db are not supported by {}",
+            key.provider
+        )));
+    }
+    if key.provider == Provider::Amazon {
+        // Headers that are not signed can be added after signing, but the
+        // others would invalidate the signature.
+        let mut extra_headers = config.extra_headers.iter().flatten();
+        if let Some((name, _)) = extra_headers.find(|(name, _)| {
+            let name = name.to_lowercase();
+            matches!(name.as_str(), "authorization" | "content-type" | "host")
+                || name.starts_with("x-amz-")
+        }) {
+            return Err(Error::InvalidConfig(format!(
+                "extra header {name} is signed and cannot be set for Amazon"
+            )));
+        }
+        let mut parts = crate::amazon::request_parts(address, key, config, model, text, time)?;
+        crate::insert_extra_headers(&mut parts.headers, &config.extra_headers)?;
+        return Ok(parts);
+    }
+    let speed = speed(&key.provider, config)?;
     let mut body = json!({});
-    if key.provider == Provider::OpenAI {
+    if is_openai(&key.provider) {
         body["input"] = Value::String(text.to_string());
     } else if key.provider == Provider::Google {
+        let name = match config.input_type {
+            InputType::Text => "text",
+            InputType::Ssml => "ssml",
+        };
         body["input"] = json!({
-            "text": text.to_string()
+            name: text.to_string()
         });
     } else {
         body["text"] = Value::String(text.to_string());
     }
     if let Some(model) = &model {
-        body["model"] = Value::String(model.to_string());
+        let name = if key.provider == Provider::ElevenLabs {
+            "model_id"
+        } else {
+            "model"
+        };
+        body[name] = Value::String(model.to_string());
     }
     if let Some(voice) = &config.voice {
-        if key.provider == Provider::OpenAI {
+        if is_openai(&key.provider) {
             body["voice"] = Value::String(voice.clone());
         } else if key.provider == Provider::Google {
             body["voice"] = json!({
                 "name": voice.clone()
             });
-            if let Some(language_code) = &config.language_code {
-                body["voice"]["languageCode"] = Value::String(language_code.clone());
+            let language_code = config
+                .language_code
+                .as_deref()
+                .or_else(|| voice_language_code(voice));
+            if let Some(language_code) = language_code {
+                body["voice"]["languageCode"] = Value::from(language_code);
             }
-            body["audioConfig"] = json!({
-                "audioEncoding": "LINEAR16",
-                "pitch": 0,
-                "speakingRate": 1
-            });
         } else if key.provider == Provider::DeepInfra {
             body["preset_voice"] = Value::String(voice.clone());
+        } else if key.provider == Provider::Hyperbolic {
+            // For example, "EN-US" for language "EN".
+            let language = voice.split('-').next().unwrap();
+            body["language"] = Value::String(language.to_string());
+            body["speaker"] = Value::String(voice.clone());
+        } else if key.provider == Provider::ElevenLabs {
+            // The voice is part of the address.
         } else {
-            panic!("Unsupported TTS provider: {}", key.provider);
+            return Err(Error::UnsupportedProvider(key.provider.clone()));
         }
     }
-    if let Some(speed) = config.speed {
-        body["speed"] = Value::from(speed);
+    if key.provider == Provider::Google {
+        let (encoding, _) = google_encoding(config.output_format.as_deref())?;
+        body["audioConfig"] = json!({
+            "audioEncoding": encoding,
+            "pitch": config.pitch.unwrap_or(0.0),
+            "speakingRate": speed.unwrap_or(1.0),
+        });
+        if let Some(volume_gain_db) = config.volume_gain_db {
+            body["audioConfig"]["volumeGainDb"] = Value::from(volume_gain_db);
+        }
+    } else if let Some(speed) = speed {
+        if key.provider == Provider::ElevenLabs {
+            body["voice_settings"] = json!({"speed": speed});
+        } else {
+            body["speed"] = Value::from(speed);
+        }
     }
-    if let Some(output_format) = &config.output_format {
-        body["output_format"] = Value::String(output_format.clone());
+    if key.provider == Provider::ElevenLabs {
+        // The output format is part of the address.
+    } else if key.provider == Provider::Google {
+        // The output format is part of the audio config.
+    } else if let Some(output_format) = &config.output_format {
+        let name = if is_openai(&key.provider) {
+            "response_format"
+        } else {
+            "output_format"
+        };
+        body[name] = Value::String(output_format.clone());
     }
     if let Some(other) = &config.other {
         for (key, value) in other {
-            body[key] = value.clone();
+            match (body.get_mut(key), value) {
+                // Keep fields such as `voice_settings.speed` that were set from
+                // the config.
+                (Some(Value::Object(fields)), Value::Object(other_fields)) => {
+                    for (name, value) in other_fields {
+                        fields.insert(name.clone(), value.clone());
+                    }
+                }
+                _ => body[key] = value.clone(),
+            }
         }
     }
-    let headers = if key.provider == Provider::Google {
-        let mut headers = request_headers(key)?;
-        headers.remove("Authorization");
-        headers
+    let mut headers = if key.provider == Provider::Google {
+        // The key is in the address.
+        crate::request_headers_with_auth(key, AuthScheme::Query)?
     } else {
         request_headers(key)?
     };
-    tracing::debug!("Requesting text-to-speech: {body}");
-    let client = reqwest::Client::new();
-    let resp = client
-        .post(address)
-        .headers(headers)
-        .json(&body)
-        .send()
+    crate::insert_idempotency_key(&mut headers, &key.provider, config.retry.as_ref())?;
+    crate::insert_extra_headers(&mut headers, &config.extra_headers)?;
+    Ok(RequestParts {
+        address,
+        headers,
+        body,
+    })
+}
+
+/// Build the request that `tts` would send, without sending it.
+///
+/// For example, to check that the text is sent as `input.text` to Google.
+pub fn build_tts_request(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<(Url, HeaderMap, Value), Error> {
+    request_parts(key, config, model, text)?.into_tuple()
+}
+
+/// Like `build_tts_request`, where Amazon requests are signed for `time`
+/// instead of the current time, for example to reproduce a signature.
+pub fn build_tts_request_at(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+    time: SystemTime,
+) -> Result<(Url, HeaderMap, Value), Error> {
+    request_parts_at(key, config, model, text, time)?.into_tuple()
+}
+
+async fn request_tts(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<Response, Error> {
+    let parts = request_parts(key, config, model, text)?;
+    tracing::debug!("Requesting text-to-speech: {}", parts.body);
+    let client = crate::client(&config.client)?;
+    let request = client
+        .post(parts.address)
+        .headers(parts.headers)
+        .json(&parts.body);
+    let span = crate::request_span("tts", &key.provider, model);
+    let resp = crate::send(request, config.retry.as_ref(), config.timeout)
+        .instrument(span)
         .await?;
+    Ok(resp)
+}
+
+pub async fn tts(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<SpeechResponse, Error> {
+    let resp = request_tts(key, config, model, text).await?;
     let speech_response = SpeechResponse {
         provider: key.provider.clone(),
-        resp: resp.bytes().await?,
+        status: resp.status().into(),
+        output_format: config.output_format.clone(),
+        rate_limit: RateLimitInfo::from_headers(resp.headers()),
+        content_type: crate::content_type(resp.headers()),
+        resp: read_audio(resp).await?,
     };
     Ok(speech_response)
 }
+
+/// Backend for text-to-speech, which can be replaced by a mock in tests.
+///
+/// `HttpBackend` sends the requests to the provider.
+pub trait TtsBackend {
+    fn tts(
+        &self,
+        config: &TTSConfig,
+        model: Option<&str>,
+        text: &str,
+    ) -> impl Future<Output = Result<Speech, Error>> + Send;
+}
+
+/// Text-to-speech where the audio is returned in chunks while it is generated.
+///
+/// This allows starting playback before the synthesis has finished. Only
+/// OpenAI, Azure, and Together AI stream the audio. For other providers, the
+/// complete audio is returned as a single chunk, so that callers can use the
+/// same code path.
+pub async fn tts_stream(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>, Error> {
+    if !is_openai(&key.provider) {
+        let speech = tts(key, config, model, text).await?.structured()?;
+        return Ok(Box::pin(futures::stream::once(async { Ok(speech.audio) })));
+    }
+    let resp = request_tts(key, config, model, text).await?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(Error::Api {
+            provider: key.provider.clone(),
+            status: status.into(),
+            message: resp.text().await?,
+        });
+    }
+    let mut chunks = resp.bytes_stream();
+    let stream = stream! {
+        let mut received = vec![];
+        while let Some(chunk) = chunks.next().await {
+            match chunk {
+                Ok(chunk) => {
+                    received.extend_from_slice(&chunk);
+                    yield Ok(chunk);
+                }
+                Err(e) => {
+                    yield Err(Error::PartialAudio {
+                        bytes: Bytes::from(received),
+                        source: Box::new(Error::from(e)),
+                    });
+                    break;
+                }
+            }
+        }
+    };
+    Ok(Box::pin(stream))
+}
+
+/// Read the body of the response.
+///
+/// Returns `Error::PartialAudio` with the bytes that were received when the
+/// connection fails before the body is complete.
+async fn read_audio(resp: Response) -> Result<Bytes, Error> {
+    let mut received = vec![];
+    let mut chunks = resp.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => received.extend_from_slice(&chunk),
+            Err(e) => {
+                return Err(Error::PartialAudio {
+                    bytes: Bytes::from(received),
+                    source: Box::new(Error::from(e)),
+                })
+            }
+        }
+    }
+    Ok(Bytes::from(received))
+}
+
+fn push_chunk(chunks: &mut Vec<String>, current: &mut String) {
+    let trimmed = current.trim();
+    if !trimmed.is_empty() {
+        chunks.push(trimmed.to_string());
+    }
+    current.clear();
+}
+
+/// Split `text` into chunks of at most `max_chars` characters.
+///
+/// Chunks end at sentence boundaries where possible. Sentences that are
+/// longer than `max_chars` are split at whitespace and, if that's not enough,
+/// in the middle of a word.
+pub fn split_text(text: &str, max_chars: usize) -> Vec<String> {
+    static SENTENCE_END: LazyLock<regex::Regex> =
+        LazyLock::new(|| regex::Regex::new(r"[.!?]+\s+").unwrap());
+    let max_chars = max_chars.max(1);
+    let mut sentences = vec![];
+    let mut start = 0;
+    for m in SENTENCE_END.find_iter(text) {
+        sentences.push(&text[start..m.end()]);
+        start = m.end();
+    }
+    sentences.push(&text[start..]);
+
+    let mut chunks = vec![];
+    let mut current = String::new();
+    for sentence in sentences {
+        if current.chars().count() + sentence.trim_end().chars().count() <= max_chars {
+            current.push_str(sentence);
+            continue;
+        }
+        push_chunk(&mut chunks, &mut current);
+        if sentence.trim_end().chars().count() <= max_chars {
+            current.push_str(sentence);
+            continue;
+        }
+        for word in sentence.split_inclusive(char::is_whitespace) {
+            if current.chars().count() + word.trim_end().chars().count() > max_chars {
+                push_chunk(&mut chunks, &mut current);
+            }
+            let mut word = word;
+            while word.trim_end().chars().count() > max_chars {
+                let split = word.char_indices().nth(max_chars).unwrap().0;
+                chunks.push(word[..split].to_string());
+                word = &word[split..];
+            }
+            current.push_str(word);
+        }
+    }
+    push_chunk(&mut chunks, &mut current);
+    chunks
+}
+
+/// Return the format and data chunks of a WAV file.
+fn wav_chunks(audio: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if audio.len() < 12 || &audio[..4] != b"RIFF" || &audio[8..12] != b"WAVE" {
+        return Err(Error::Decode("invalid WAV header".to_string()));
+    }
+    let mut fmt = None;
+    let mut pos = 12;
+    while pos + 8 <= audio.len() {
+        let id = &audio[pos..pos + 4];
+        let size = u32::from_le_bytes(audio[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let start = pos + 8;
+        // Streamed WAV files may contain a placeholder size.
+        let end = start.saturating_add(size).min(audio.len());
+        if id == b"fmt " {
+            fmt = Some(&audio[start..end]);
+        } else if id == b"data" {
+            let fmt = fmt.ok_or(Error::Decode("no fmt chunk in WAV".to_string()))?;
+            return Ok((fmt, &audio[start..end]));
+        }
+        pos = end + (size % 2);
+    }
+    Err(Error::Decode("no data chunk in WAV".to_string()))
+}
+
+/// Sample rate and duration in seconds of WAV audio.
+fn wav_info(audio: &[u8]) -> Option<(u32, f64)> {
+    let (fmt, data) = wav_chunks(audio).ok()?;
+    if fmt.len() < 12 {
+        return None;
+    }
+    let sample_rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+    let byte_rate = u32::from_le_bytes(fmt[8..12].try_into().unwrap());
+    if byte_rate == 0 {
+        return None;
+    }
+    Some((sample_rate, data.len() as f64 / byte_rate as f64))
+}
+
+/// Combine WAV files into one by concatenating the samples.
+fn concat_wav(parts: &[Bytes]) -> Result<Bytes, Error> {
+    let mut fmt: &[u8] = &[];
+    let mut data = vec![];
+    for (i, part) in parts.iter().enumerate() {
+        let (part_fmt, part_data) = wav_chunks(part)?;
+        if i == 0 {
+            fmt = part_fmt;
+        }
+        data.extend_from_slice(part_data);
+    }
+    Ok(wav(fmt, &data))
+}
+
+/// WAV file with the `fmt ` and `data` chunks.
+fn wav(fmt: &[u8], data: &[u8]) -> Bytes {
+    let mut out = Vec::with_capacity(data.len() + fmt.len() + 20);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&((4 + 8 + fmt.len() + 8 + data.len()) as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&(fmt.len() as u32).to_le_bytes());
+    out.extend_from_slice(fmt);
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    Bytes::from(out)
+}
+
+/// Combine the audio of multiple `Speech`s into one.
+///
+/// WAV files are stitched by combining the samples under one header. MP3, AAC,
+/// and PCM consist of independent frames, so these are concatenated. Other
+/// formats, such as opus and flac, cannot be concatenated and return
+/// `Error::InvalidConfig`.
+pub fn concat_speech(speeches: Vec<Speech>) -> Result<Speech, Error> {
+    let mut speeches = speeches;
+    if speeches.len() == 1 {
+        return Ok(speeches.remove(0));
+    }
+    let first = match speeches.first() {
+        Some(first) => first,
+        None => return Err(Error::InvalidConfig("no audio to combine".to_string())),
+    };
+    let parts = speeches.iter().map(|s| s.audio.clone()).collect::<Vec<_>>();
+    let file_format = first.file_format.clone();
+    let audio = if first.audio.starts_with(b"RIFF") {
+        concat_wav(&parts)?
+    } else if ["mp3", "aac", "pcm"].contains(&file_format.as_str()) {
+        Bytes::from(parts.concat())
+    } else {
+        return Err(Error::InvalidConfig(format!(
+            "cannot combine audio in {file_format} format; use mp3 or wav"
+        )));
+    };
+    let duration_seconds = speeches
+        .iter()
+        .map(|s| s.duration_seconds)
+        .sum::<Option<f64>>();
+    Ok(Speech {
+        request_id: None,
+        file_format,
+        audio,
+        timepoints: vec![],
+        sample_rate: first.sample_rate,
+        duration_seconds,
+    })
+}
+
+/// Text-to-speech for texts that are longer than the provider allows.
+///
+/// Splits the text into chunks of at most `config.max_chars` characters via
+/// `split_text`, synthesizes each chunk, and combines the audio via
+/// `concat_speech`. This is useful to narrate, for example, whole articles.
+/// Use mp3 or wav as output format since other formats cannot be combined.
+pub async fn tts_long(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+) -> Result<Speech, Error> {
+    let max_chars = config.max_chars.unwrap_or(DEFAULT_MAX_CHARS);
+    let config = &TTSConfig {
+        retry: crate::fan_out_retry(&config.retry),
+        ..config.clone()
+    };
+    let mut speeches = vec![];
+    for chunk in split_text(text, max_chars) {
+        let speech = tts(key, config, model, &chunk).await?.structured()?;
+        speeches.push(speech);
+    }
+    concat_speech(speeches)
+}
+
+/// Text-to-speech in the given output formats, by format.
+///
+/// None of the providers return more than one format per request, so
+/// `Error::InvalidConfig` is returned when multiple formats are requested.
+/// This avoids silently sending, and paying for, one request per format. The
+/// format replaces `config.output_format`.
+pub async fn tts_formats(
+    key: &Key,
+    config: &TTSConfig,
+    model: Option<&str>,
+    text: &str,
+    formats: &[&str],
+) -> Result<HashMap<String, Speech>, Error> {
+    let format = match formats {
+        [format] => format.to_string(),
+        [] => {
+            return Err(Error::InvalidConfig(
+                "at least one output format is required".to_string(),
+            ))
+        }
+        _ => {
+            return Err(Error::InvalidConfig(format!(
+                "multiple output formats per request are not supported by {}",
+                key.provider
+            )))
+        }
+    };
+    let config = TTSConfig {
+        output_format: Some(format.clone()),
+        ..config.clone()
+    };
+    let speech = tts(key, &config, model, text).await?.structured()?;
+    Ok(HashMap::from([(format, speech)]))
+}
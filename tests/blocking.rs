@@ -0,0 +1,51 @@
+#![cfg(feature = "blocking")]
+extern crate transformrs;
+
+mod common;
+
+use transformrs::Key;
+use transformrs::Message;
+use transformrs::Provider;
+
+#[test]
+fn test_blocking_chat_completion() {
+    let body = serde_json::json!({
+        "object": "chat.completion",
+        "created": 0,
+        "model": "foo",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": "hello world"},
+            "finish_reason": "stop"
+        }]
+    });
+    let responses = vec![common::response(200, &body.to_string())];
+    let (address, requests) = common::serve(responses);
+    let provider = Provider::Other(address);
+    let key = Key::new(provider.clone(), "foo");
+    let messages = vec![Message::user("Hello")];
+    let resp = transformrs::blocking::chat_completion(&provider, &key, "foo", &messages)
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.choices[0].message.content.to_string(), "hello world");
+    assert!(requests.lock().unwrap()[0].starts_with("POST /chat/completions"));
+}
+
+#[test]
+fn test_blocking_tts_partial_audio() {
+    let truncated =
+        "HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 100\r\nConnection: close\r\n\r\nID3"
+            .to_string();
+    let (address, key, _requests) = common::serve_key(Provider::OpenAI, vec![truncated]);
+    let config = transformrs::text_to_speech::TTSConfig::builder()
+        .base_url(&address)
+        .build();
+    let err = transformrs::blocking::tts(&key, &config, Some("tts-1"), "Hi")
+        .err()
+        .unwrap();
+    match err {
+        transformrs::Error::PartialAudio { bytes, .. } => assert_eq!(&bytes[..], b"ID3"),
+        err => panic!("expected partial audio, got {err}"),
+    }
+}
@@ -3,12 +3,17 @@ extern crate transformrs;
 mod common;
 
 use futures_util::stream::StreamExt;
-use std::error::Error;
+use std::collections::HashMap;
+use std::time::Duration;
 use transformrs::chat;
 use transformrs::Content;
+use transformrs::Error;
 use transformrs::Key;
 use transformrs::Message;
 use transformrs::Provider;
+use transformrs::RetryConfig;
+use transformrs::Role;
+use transformrs::SubContent;
 
 const MODEL: &str = "meta-llama/Llama-3.3-70B-Instruct";
 
@@ -24,8 +29,8 @@ fn canonicalize_content(content: &Content) -> String {
 
 fn hello_messages() -> Vec<Message> {
     vec![
-        Message::from_str("system", "You are a helpful assistant."),
-        Message::from_str("user", "This is a test. Please respond with 'hello world'."),
+        Message::from_str("system", "You are a helpful assistant.").unwrap(),
+        Message::from_str("user", "This is a test. Please respond with 'hello world'.").unwrap(),
     ]
 }
 
@@ -34,7 +39,7 @@ async fn test_chat_completion_no_stream(
     provider: Provider,
     model: &str,
     expected: Option<&str>,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), Error> {
     common::init_tracing();
     let keys = transformrs::load_keys(".env");
     let key = keys.for_provider(&provider).expect("no key found");
@@ -61,19 +66,19 @@ async fn test_chat_completion_no_stream(
 async fn test_hello_chat_completion_no_stream(
     provider: Provider,
     model: &str,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), Error> {
     test_chat_completion_no_stream(hello_messages(), provider, model, Some("hello world")).await
 }
 
 async fn test_image_url_chat_completion_no_stream(
     provider: Provider,
     model: &str,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), Error> {
     let image_url = "https://transformrs.org/sunset.jpg";
     let messages = vec![
-        Message::from_str("system", "You are a helpful assistant."),
-        Message::from_str("user", "Describe this image in one sentence."),
-        Message::from_image_url("user", image_url),
+        Message::from_str("system", "You are a helpful assistant.").unwrap(),
+        Message::from_str("user", "Describe this image in one sentence.").unwrap(),
+        Message::from_image_url("user", image_url).unwrap(),
     ];
     test_chat_completion_no_stream(messages, provider, model, None).await
 }
@@ -81,15 +86,58 @@ async fn test_image_url_chat_completion_no_stream(
 async fn test_image_chat_completion_no_stream(
     provider: Provider,
     model: &str,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), Error> {
     let image = include_bytes!("sunset.jpg");
     let messages = vec![
-        Message::from_str("user", "Describe this image in one sentence."),
-        Message::from_image_bytes("user", "jpeg", image),
+        Message::from_str("user", "Describe this image in one sentence.").unwrap(),
+        Message::from_image_bytes("user", "jpeg", image).unwrap(),
     ];
     test_chat_completion_no_stream(messages, provider, model, None).await
 }
 
+#[tokio::test]
+async fn test_chat_completion_no_stream_anthropic() {
+    test_hello_chat_completion_no_stream(Provider::Anthropic, "claude-3-5-haiku-latest")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_chat_completion_no_stream_anthropic_error() {
+    let out = test_hello_chat_completion_no_stream(Provider::Anthropic, "foo").await;
+    let err = out.unwrap_err();
+    println!("{}", err);
+    assert!(err.to_string().contains("model: foo"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_no_stream_anthropic_image() {
+    test_image_chat_completion_no_stream(Provider::Anthropic, "claude-3-5-haiku-latest")
+        .await
+        .unwrap();
+}
+
+#[test]
+fn test_anthropic_image_blocks() {
+    let key = Key::new(Provider::Anthropic, "foo");
+    let config = chat::ChatConfig::default();
+    let messages = vec![
+        Message::from_image_bytes("user", "png", b"png").unwrap(),
+        Message::from_image_url("user", "https://example.com/sunset.jpg").unwrap(),
+    ];
+    let (_, _, body) =
+        chat::build_chat_request(&Provider::Anthropic, &key, &config, "claude", &messages).unwrap();
+    let source = |i: usize| body["messages"][i]["content"][0]["source"].clone();
+    assert_eq!(
+        source(0),
+        serde_json::json!({"type": "base64", "media_type": "image/png", "data": "cG5n"})
+    );
+    assert_eq!(
+        source(1),
+        serde_json::json!({"type": "url", "url": "https://example.com/sunset.jpg"})
+    );
+}
+
 #[tokio::test]
 async fn test_chat_completion_no_stream_deepinfra() {
     test_hello_chat_completion_no_stream(Provider::DeepInfra, MODEL)
@@ -193,6 +241,27 @@ async fn test_chat_completion_no_stream_openai_image() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_chat_completion_with_config_deepinfra() {
+    common::init_tracing();
+    let provider = Provider::DeepInfra;
+    let key = transformrs::load_keys(".env")
+        .for_provider(&provider)
+        .unwrap();
+    let config = chat::ChatConfig {
+        temperature: Some(0.0),
+        max_tokens: Some(1),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, MODEL, &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.choices[0].finish_reason, Some("length".to_string()));
+}
+
 #[tokio::test]
 async fn test_chat_completion_no_stream_other() {
     let provider = Provider::Other("https://api.deepinfra.com/v1/openai".to_string());
@@ -201,11 +270,774 @@ async fn test_chat_completion_no_stream_other() {
         .unwrap();
 }
 
+#[test]
+fn test_message_role() {
+    let message = Message::system("You are a helpful assistant.");
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["role"], "system");
+    assert_eq!(Message::from_str("user", "hi").unwrap().role, Role::User);
+    assert!("systen".parse::<Role>().is_err());
+}
+
+#[test]
+fn test_message_unknown_role() {
+    let result = Message::from_str("systen", "You are a helpful assistant.");
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    let result = Message::from_image_url("systen", "https://example.com/sunset.jpg");
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+
+    // Roles in responses are kept, since providers may add new ones.
+    let json = serde_json::json!({"role": "developer", "content": "Hi"});
+    let message = serde_json::from_value::<Message>(json).unwrap();
+    assert_eq!(message.role, Role::Other("developer".to_string()));
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["role"], "developer");
+}
+
+#[test]
+fn test_message_content_parts() {
+    let content = Content::Collection(vec![
+        SubContent::new("text", "Describe this image."),
+        SubContent::new("image_url", "https://transformrs.org/sunset.jpg"),
+    ]);
+    let message = Message::from_content(Role::User, content);
+    let json = serde_json::to_value(&message).unwrap();
+    let expected = serde_json::json!([
+        {"type": "text", "text": "Describe this image."},
+        {"type": "image_url", "image_url": {"url": "https://transformrs.org/sunset.jpg"}}
+    ]);
+    assert_eq!(json["content"], expected);
+    let message = serde_json::from_value::<Message>(json).unwrap();
+    assert!(matches!(message.content, Content::Collection(parts) if parts.len() == 2));
+}
+
+#[test]
+fn test_chat_completion_usage() {
+    let json = r#"{
+        "id": "1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "foo",
+        "choices": [],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3}
+    }"#;
+    let completion = serde_json::from_str::<chat::ChatCompletion>(json).unwrap();
+    assert_eq!(completion.usage.unwrap().total_tokens, 3);
+
+    let json = r#"{"object": "chat.completion", "created": 0, "model": "foo", "choices": []}"#;
+    let completion = serde_json::from_str::<chat::ChatCompletion>(json).unwrap();
+    assert!(completion.usage.is_none());
+}
+
+fn completion_json(content: &str) -> String {
+    serde_json::json!({
+        "id": "1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "foo",
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": content},
+            "finish_reason": "stop"
+        }]
+    })
+    .to_string()
+}
+
+fn local_key(address: &str) -> (Provider, Key) {
+    let provider = Provider::Other(address.to_string());
+    let key = Key::new(provider.clone(), "foo");
+    (provider, key)
+}
+
+#[tokio::test]
+async fn test_chat_completion_retry() {
+    let responses = vec![
+        common::response(429, r#"{"error": {"message": "rate limited"}}"#),
+        common::response(503, r#"{"error": {"message": "unavailable"}}"#),
+        common::response(200, &completion_json("hello world")),
+    ];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        retry: Some(RetryConfig {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            respect_retry_after: true,
+            idempotency_key: None,
+        }),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.choices[0].message.content.to_string(), "hello world");
+    assert_eq!(requests.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_chat_completion_idempotency_key() {
+    let responses = vec![
+        common::response(503, r#"{"error": {"message": "unavailable"}}"#),
+        common::response(200, &completion_json("hello world")),
+        common::response(200, &completion_json("hello world")),
+    ];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let retry = RetryConfig {
+        base_delay: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        retry: Some(retry.clone()),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    chat::chat_completion_with_config(&Provider::OpenAI, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let idempotency_key = |request: &str| {
+        request
+            .lines()
+            .find_map(|line| line.strip_prefix("idempotency-key: "))
+            .map(|value| value.to_string())
+    };
+    let (first, second) = {
+        let requests = requests.lock().unwrap();
+        (
+            idempotency_key(&requests[0]).unwrap(),
+            idempotency_key(&requests[1]).unwrap(),
+        )
+    };
+    assert_eq!(first, second);
+    assert_eq!(first.len(), 36);
+
+    let config = chat::ChatConfig {
+        retry: Some(RetryConfig {
+            idempotency_key: Some("my-key".to_string()),
+            ..retry
+        }),
+        ..config
+    };
+    chat::chat_completion_with_config(&Provider::OpenAI, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[2].clone();
+    assert_eq!(idempotency_key(&request).unwrap(), "my-key");
+}
+
+#[tokio::test]
+async fn test_chat_completion_batch_idempotency_key() {
+    let responses = vec![
+        common::response(200, &completion_json("hello")),
+        common::response(200, &completion_json("world")),
+    ];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        retry: Some(RetryConfig {
+            idempotency_key: Some("my-key".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let prompts = vec![hello_messages(), hello_messages()];
+    let results = chat::chat_completion_batch_with_config(
+        &Provider::OpenAI,
+        &key,
+        &config,
+        "foo",
+        &prompts,
+        1,
+    )
+    .await;
+    assert!(results.iter().all(|result| result.is_ok()));
+    // Otherwise, the provider would return the first completion twice.
+    let keys = requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|request| {
+            request
+                .lines()
+                .find_map(|line| line.strip_prefix("idempotency-key: "))
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(keys.len(), 2);
+    assert_ne!(keys[0], keys[1]);
+    assert!(!keys.contains(&"my-key".to_string()));
+}
+
+#[tokio::test]
+async fn test_chat_completion_retry_fail_fast() {
+    let responses = vec![common::response(
+        401,
+        r#"{"error": {"message": "invalid key"}}"#,
+    )];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        retry: Some(RetryConfig::default()),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let err = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap_err();
+    assert!(matches!(err, Error::Api { status: 401, .. }));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_chat_completion_custom_client() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert("x-custom", "foo".parse().unwrap());
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    let config = chat::ChatConfig {
+        client: Some(client),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    assert!(requests.lock().unwrap()[0].contains("x-custom: foo"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_response_format() {
+    let responses = vec![common::response(200, &completion_json("{}"))];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let format = chat::ResponseFormat::JsonSchema {
+        json_schema: chat::JsonSchema {
+            name: "answer".to_string(),
+            description: None,
+            schema: serde_json::json!({"type": "object"}),
+            strict: Some(true),
+        },
+    };
+    let config = chat::ChatConfig {
+        response_format: Some(format),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["response_format"]["type"], "json_schema");
+    assert_eq!(body["response_format"]["json_schema"]["name"], "answer");
+
+    let key = Key::new(Provider::Anthropic, "foo");
+    let resp =
+        chat::chat_completion_with_config(&Provider::Anthropic, &key, &config, "foo", &messages)
+            .await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_chat_completion_n() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("a")).unwrap();
+    let mut second = json["choices"][0].clone();
+    second["index"] = serde_json::json!(1);
+    second["message"]["content"] = serde_json::json!("b");
+    second["finish_reason"] = serde_json::json!("length");
+    json["choices"].as_array_mut().unwrap().push(second);
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        n: Some(2),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert!(requests.lock().unwrap()[0].contains(r#""n":2"#));
+    assert_eq!(resp.choices.len(), 2);
+    assert_eq!(resp.choices[1].index, 1);
+    assert_eq!(resp.choices[1].message.content.to_string(), "b");
+    assert_eq!(resp.choices[1].finish_reason.as_deref(), Some("length"));
+    assert_eq!(resp.choices[0].reason(), Some(chat::FinishReason::Stop));
+    assert_eq!(resp.choices[1].reason(), Some(chat::FinishReason::Length));
+}
+
+#[tokio::test]
+async fn test_chat_completion_base_url() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = chat::ChatConfig {
+        base_url: Some(format!("{address}/")),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    chat::chat_completion_with_config(&Provider::OpenAI, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    assert!(requests.lock().unwrap()[0].starts_with("POST /v1/chat/completions"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_openai_organization() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let key = Key {
+        organization: Some("org-1".to_string()),
+        project: Some("proj-1".to_string()),
+        ..key
+    };
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    chat::chat_completion_with_config(&Provider::OpenAI, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains("openai-organization: org-1"));
+    assert!(request.contains("openai-project: proj-1"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_azure() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, key, requests) = common::serve_key(Provider::Azure, responses);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&Provider::Azure, &key, "gpt-4o", &messages).await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
+
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    chat::chat_completion_with_config(&Provider::Azure, &key, &config, "gpt-4o", &messages)
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    let expected = format!(
+        "POST /openai/deployments/gpt-4o/chat/completions?api-version={}",
+        transformrs::AZURE_API_VERSION
+    );
+    assert!(request.starts_with(&expected));
+    assert!(request.contains("api-key: foo"));
+    assert!(!request.contains("authorization"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_anthropic_local() {
+    let body = serde_json::json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude",
+        "content": [{"type": "text", "text": "hello world"}],
+        "stop_reason": "end_turn",
+        "usage": {"input_tokens": 3, "output_tokens": 2}
+    });
+    let responses = vec![common::response(200, &body.to_string())];
+    let (address, key, requests) = common::serve_key(Provider::Anthropic, responses);
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp =
+        chat::chat_completion_with_config(&Provider::Anthropic, &key, &config, "claude", &messages)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/messages"));
+    assert!(request.contains("x-api-key: foo"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["system"], "You are a helpful assistant.");
+    assert_eq!(body["max_tokens"], 4096);
+    assert_eq!(body["messages"].as_array().unwrap().len(), 1);
+
+    assert_eq!(resp.choices[0].message.content.to_string(), "hello world");
+    assert_eq!(resp.choices[0].reason(), Some(chat::FinishReason::Stop));
+    assert_eq!(resp.usage.unwrap().total_tokens, 5);
+}
+
+#[tokio::test]
+async fn test_chat_completion_seed() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("a")).unwrap();
+    json["system_fingerprint"] = serde_json::json!("fp_1");
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        seed: Some(42),
+        temperature: Some(0.0),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert!(requests.lock().unwrap()[0].contains(r#""seed":42"#));
+    assert_eq!(resp.system_fingerprint.as_deref(), Some("fp_1"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_structured_errors() {
+    let html = "<html>Bad Gateway</html>";
+    let responses = vec![
+        common::response(502, html),
+        common::response(404, r#"{"detail": "Model not found"}"#),
+        common::response(200, r#"{"error": {"message": "invalid model"}}"#),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let expected = [
+        (502, html),
+        (404, "Model not found"),
+        (200, "invalid model"),
+    ];
+    for (expected_status, expected_message) in expected {
+        let err = chat::chat_completion(&provider, &key, "foo", &messages)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap_err();
+        match err {
+            Error::Api {
+                status, message, ..
+            } => {
+                assert_eq!(status, expected_status);
+                assert_eq!(message, expected_message);
+            }
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_chat_completion_logprobs() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("hi")).unwrap();
+    json["choices"][0]["logprobs"] = serde_json::json!({
+        "content": [{
+            "token": "hi",
+            "logprob": -0.1,
+            "bytes": [104, 105],
+            "top_logprobs": [{"token": "hi", "logprob": -0.1, "bytes": [104, 105]}]
+        }]
+    });
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        logprobs: Some(true),
+        top_logprobs: Some(1),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains(r#""logprobs":true"#));
+    assert!(request.contains(r#""top_logprobs":1"#));
+    let logprobs = resp.choices[0].logprobs.clone().unwrap().content.unwrap();
+    assert_eq!(logprobs[0].token, "hi");
+    assert_eq!(logprobs[0].logprob, -0.1);
+    assert_eq!(logprobs[0].top_logprobs.len(), 1);
+}
+
+#[tokio::test]
+async fn test_chat_completion_other_address() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&format!("{address}/v1/"));
+    let messages = hello_messages();
+    chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert!(requests.lock().unwrap()[0].starts_with("POST /v1/chat/completions "));
+}
+
+#[tokio::test]
+async fn test_chat_completion_timeout() {
+    // Accepts the connection but never responds.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages).await;
+    assert!(matches!(resp, Err(Error::Timeout)));
+}
+
+fn weather_tool() -> chat::Tool {
+    chat::Tool {
+        name: "get_weather".to_string(),
+        description: Some("Get the current weather for a city.".to_string()),
+        parameters: serde_json::json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"]
+        }),
+    }
+}
+
+#[tokio::test]
+async fn test_chat_completion_tool_calls() {
+    let body = serde_json::json!({
+        "object": "chat.completion",
+        "created": 0,
+        "model": "foo",
+        "choices": [{
+            "index": 0,
+            "message": {
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\": \"Paris\"}"}
+                }]
+            },
+            "finish_reason": "tool_calls"
+        }]
+    });
+    let responses = vec![common::response(200, &body.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig {
+        tools: Some(vec![weather_tool()]),
+        ..Default::default()
+    };
+    let messages = vec![Message::from_str("user", "What is the weather in Paris?").unwrap()];
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let tool_calls = resp.choices[0].message.tool_calls.clone().unwrap();
+    assert_eq!(tool_calls[0].id, "call_1");
+    assert_eq!(tool_calls[0].name, "get_weather");
+    assert_eq!(
+        tool_calls[0].arguments,
+        serde_json::json!({"city": "Paris"})
+    );
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains(r#""type":"function""#));
+    assert!(request.contains(r#""name":"get_weather""#));
+}
+
+#[test]
+fn test_anthropic_tools() {
+    let key = Key::new(Provider::Anthropic, "foo");
+    let tool = chat::Tool {
+        description: None,
+        ..weather_tool()
+    };
+    let config = chat::ChatConfig {
+        tools: Some(vec![weather_tool(), tool]),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let (_, _, body) =
+        chat::build_chat_request(&Provider::Anthropic, &key, &config, "claude", &messages).unwrap();
+    let tools = body["tools"].as_array().unwrap();
+    assert_eq!(
+        tools[0]["description"],
+        "Get the current weather for a city."
+    );
+    assert_eq!(tools[0]["input_schema"]["required"][0], "city");
+    assert!(tools[1].get("description").is_none());
+}
+
+#[test]
+fn test_tool_call_invalid_arguments() {
+    let tool_call = |arguments: &str| {
+        let json = serde_json::json!({
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "get_weather", "arguments": arguments}
+        });
+        serde_json::from_value::<transformrs::ToolCall>(json).unwrap()
+    };
+    assert_eq!(tool_call("").arguments, serde_json::json!({}));
+    // For example, when the response was cut off at `max_tokens`.
+    let truncated = tool_call(r#"{"city": "Par"#);
+    assert_eq!(truncated.arguments, serde_json::json!(r#"{"city": "Par"#));
+    let json = serde_json::to_value(&truncated).unwrap();
+    assert_eq!(json["function"]["arguments"], r#"{"city": "Par"#);
+}
+
+#[test]
+fn test_message_tool() {
+    let tool_call = transformrs::ToolCall {
+        id: "call_1".to_string(),
+        name: "get_weather".to_string(),
+        arguments: serde_json::json!({"city": "Paris"}),
+    };
+    let message = Message::assistant_with_tool_calls(vec![tool_call]);
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["role"], "assistant");
+    assert_eq!(json["tool_calls"][0]["id"], "call_1");
+    assert_eq!(
+        json["tool_calls"][0]["function"]["arguments"],
+        r#"{"city":"Paris"}"#
+    );
+
+    let message = Message::tool("call_1", "Sunny");
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"role": "tool", "content": "Sunny", "tool_call_id": "call_1"})
+    );
+}
+
+#[tokio::test]
+async fn test_chat_completion_tool_calls_openai() {
+    let provider = Provider::OpenAI;
+    let key = transformrs::load_keys(".env")
+        .for_provider(&provider)
+        .unwrap();
+    let config = chat::ChatConfig {
+        tools: Some(vec![weather_tool()]),
+        ..Default::default()
+    };
+    let messages = vec![Message::from_str("user", "What is the weather in Paris?").unwrap()];
+    let resp =
+        chat::chat_completion_with_config(&provider, &key, &config, "gpt-4o-mini", &messages)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap();
+    let tool_calls = resp.choices[0].message.tool_calls.clone().unwrap();
+    assert_eq!(tool_calls[0].name, "get_weather");
+}
+
+fn sse_response(events: &[serde_json::Value]) -> String {
+    let mut body = String::new();
+    for event in events {
+        body.push_str(&format!("data: {event}\n\n"));
+    }
+    body.push_str("data: [DONE]\n\n");
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn chunk_json(content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": "1",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "foo",
+        "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": null}]
+    })
+}
+
+#[tokio::test]
+async fn test_chat_completion_stream_events() {
+    let mut last = chunk_json("world");
+    // Groq sends the usage inside `x_groq` and may send `"usage": null`.
+    last["usage"] = serde_json::Value::Null;
+    last["x_groq"] = serde_json::json!({
+        "usage": {"prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3}
+    });
+    let events = [chunk_json("hello "), last];
+    let responses = vec![sse_response(&events), sse_response(&events)];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig::default();
+    let messages = hello_messages();
+    let mut stream =
+        chat::stream_chat_completion_events(&provider, &key, &config, "foo", &messages)
+            .await
+            .unwrap();
+    let mut content = String::new();
+    let mut usage = None;
+    while let Some(event) = stream.next().await {
+        match event {
+            chat::StreamEvent::Chunk(chunk) => {
+                content += &chunk.choices[0].delta.content.clone().unwrap();
+            }
+            chat::StreamEvent::StreamEnd { usage: end } => usage = end,
+        }
+    }
+    assert_eq!(content, "hello world");
+    assert_eq!(usage.unwrap().total_tokens, 3);
+
+    let stream = chat::stream_chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert_eq!(stream.count().await, 2);
+}
+
+#[tokio::test]
+async fn test_chat_completion_stream_keep_alive() {
+    let body = format!(
+        ": keep-alive\n\n\
+         event: ping\nid: 1\nretry: 1000\n\n\
+         data: {}\n\n\
+         : OPENROUTER PROCESSING\r\n\r\n\
+         :\n\
+         data:{}\r\n\r\n\
+         : keep-alive\n\n\
+         data: [DONE]\n\n",
+        chunk_json("hello "),
+        chunk_json("world"),
+    );
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let (address, _requests) = common::serve(vec![response]);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let stream = chat::stream_chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    let chunks: Vec<_> = stream.collect().await;
+    assert_eq!(chunks.len(), 2);
+    let content: String = chunks
+        .iter()
+        .map(|chunk| chunk.choices[0].delta.content.clone().unwrap())
+        .collect();
+    assert_eq!(content, "hello world");
+}
+
 async fn chat_completion_stream_helper(
     provider: &Provider,
     key: &Key,
     model: &str,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
+) -> Result<(), Error> {
     common::init_tracing();
     let messages = hello_messages();
     let mut stream = chat::stream_chat_completion(provider, key, model, &messages)
@@ -222,6 +1054,17 @@ async fn chat_completion_stream_helper(
     Ok(())
 }
 
+#[tokio::test]
+async fn test_chat_completion_stream_anthropic() {
+    let provider = Provider::Anthropic;
+    let key = transformrs::load_keys(".env")
+        .for_provider(&provider)
+        .unwrap();
+    chat_completion_stream_helper(&provider, &key, "claude-3-5-haiku-latest")
+        .await
+        .unwrap();
+}
+
 #[tokio::test]
 async fn test_chat_completion_stream_deepinfra() {
     let provider = Provider::DeepInfra;
@@ -265,3 +1108,921 @@ async fn test_chat_completion_stream_openai() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_chat_completion_fallback() {
+    let responses = vec![common::response(503, r#"{"error": "unavailable"}"#)];
+    let (first, first_requests) = common::serve(responses);
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (second, second_requests) = common::serve(responses);
+    let (first, key) = local_key(&first);
+    let second = Provider::Other(second);
+    let keys = transformrs::Keys { keys: vec![key] };
+    let providers = vec![Provider::Groq, first.clone(), second.clone()];
+    let model_map = HashMap::from([
+        (Provider::Groq, "llama3-8b-8192".to_string()),
+        (first, "foo".to_string()),
+        (second, "bar".to_string()),
+    ]);
+    let messages = hello_messages();
+    let resp = chat::chat_completion_fallback(&keys, &providers, &model_map, &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.choices[0].message.content.to_string(), "hello world");
+    assert_eq!(first_requests.lock().unwrap().len(), 1);
+    let request = second_requests.lock().unwrap()[0].clone();
+    assert!(request.contains(r#""model":"bar""#));
+}
+
+#[tokio::test]
+async fn test_chat_completion_fallback_all_failed() {
+    let responses = vec![common::response(500, r#"{"error": "internal"}"#)];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let keys = transformrs::Keys { keys: vec![key] };
+    let providers = vec![provider.clone(), Provider::Groq];
+    let model_map = HashMap::from([(provider, "foo".to_string())]);
+    let messages = hello_messages();
+    let err = chat::chat_completion_fallback(&keys, &providers, &model_map, &messages)
+        .await
+        .err()
+        .unwrap();
+    let errors = match &err {
+        Error::AllProvidersFailed(errors) => errors,
+        _ => panic!("unexpected error: {err}"),
+    };
+    assert_eq!(errors.len(), 2);
+    assert!(matches!(errors[0].1, Error::Api { status: 500, .. }));
+    assert!(matches!(errors[1].1, Error::MissingKey(Provider::Groq)));
+    assert!(err.to_string().contains("internal"));
+}
+
+#[tokio::test]
+async fn test_chat_completion_request_id() {
+    let header = "\r\nx-request-id: req_123\r\n";
+    let responses = vec![
+        common::response(200, &completion_json("hello world")).replacen("\r\n", header, 1),
+        common::response(500, r#"{"error": "internal"}"#).replacen("\r\n", header, 1),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert_eq!(resp.request_id(), Some("req_123"));
+    let completion = resp.structured().unwrap();
+    assert_eq!(completion.id.as_deref(), Some("1"));
+    assert_eq!(completion.request_id.as_deref(), Some("req_123"));
+
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert!(resp.structured().is_err());
+    assert_eq!(resp.request_id(), Some("req_123"));
+}
+
+#[test]
+fn test_default_chat_model() {
+    assert_eq!(Provider::OpenAI.default_chat_model(), Some("gpt-4o-mini"));
+    assert_eq!(Provider::Azure.default_chat_model(), None);
+    assert_eq!(Provider::ElevenLabs.default_chat_model(), None);
+}
+
+#[tokio::test]
+async fn test_conversation() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let mut conversation = chat::Conversation::new();
+    conversation.push_user("Say hello world.");
+    conversation.push_system("You are a helpful assistant.");
+    conversation.push_system("Be brief.");
+    let reply = conversation.complete(&provider, &key, "foo").await.unwrap();
+    assert_eq!(reply.content.to_string(), "hello world");
+    let messages = conversation.messages();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].role, Role::System);
+    assert_eq!(
+        messages[0].content.to_string(),
+        "You are a helpful assistant.\n\nBe brief."
+    );
+    assert_eq!(messages[2].role, Role::Assistant);
+    let request = requests.lock().unwrap()[0].clone();
+    assert_eq!(request.matches(r#""role":"system""#).count(), 1);
+}
+
+#[tokio::test]
+async fn test_conversation_no_choices() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("")).unwrap();
+    json["choices"] = serde_json::json!([]);
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let mut conversation = chat::Conversation::new();
+    conversation.push_user("Say hello world.");
+    let err = conversation
+        .complete(&provider, &key, "foo")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::EmptyResponse));
+    assert_eq!(conversation.messages().len(), 1);
+}
+
+#[tokio::test]
+async fn test_client_proxy() {
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (proxy, requests) = common::serve(responses);
+    let provider = Provider::Other("http://example.invalid/v1".to_string());
+    let key = Key::new(provider.clone(), "foo");
+    let client = transformrs::ClientConfig {
+        proxy: Some(proxy),
+        ..Default::default()
+    }
+    .build()
+    .unwrap();
+    let config = chat::ChatConfig {
+        client: Some(client),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.choices[0].message.content.to_string(), "hello world");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST http://example.invalid/v1/chat/completions"));
+
+    let config = transformrs::ClientConfig {
+        proxy: Some("not a url".to_string()),
+        ..Default::default()
+    };
+    assert!(matches!(config.build(), Err(Error::InvalidConfig(_))));
+}
+
+#[cfg(all(feature = "brotli", feature = "gzip"))]
+#[tokio::test]
+async fn test_accept_encoding() {
+    let responses = vec![
+        common::response(200, &completion_json("hello world")),
+        common::response(200, &completion_json("hello world")),
+    ];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    let client = transformrs::ClientConfig {
+        decompress: Some(false),
+        ..Default::default()
+    }
+    .build()
+    .unwrap();
+    let config = chat::ChatConfig {
+        client: Some(client),
+        ..Default::default()
+    };
+    chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let requests = requests.lock().unwrap();
+    assert!(requests[0].contains("accept-encoding: gzip,br"));
+    assert!(!requests[1].contains("accept-encoding"));
+}
+
+#[test]
+fn test_build_chat_request() {
+    let provider = Provider::Anthropic;
+    let key = Key::new(provider.clone(), "foo");
+    let config = chat::ChatConfig::default();
+    let messages = hello_messages();
+    let (url, headers, body) =
+        chat::build_chat_request(&provider, &key, &config, "claude", &messages).unwrap();
+    assert_eq!(url.as_str(), "https://api.anthropic.com/v1/messages");
+    assert_eq!(headers["x-api-key"], "foo");
+    assert_eq!(body["system"], "You are a helpful assistant.");
+    assert_eq!(body["model"], "claude");
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let headers = "\r\nx-ratelimit-limit-requests: 100\r\nx-ratelimit-remaining-requests: 99\r\nx-ratelimit-remaining-tokens: 5000\r\nx-ratelimit-reset-tokens: 6m0s\r\n";
+    let anthropic = "\r\nanthropic-ratelimit-requests-remaining: 7\r\n";
+    let responses = vec![
+        common::response(200, &completion_json("hello world")).replacen("\r\n", headers, 1),
+        common::response(200, &completion_json("hello world")).replacen("\r\n", anthropic, 1),
+        common::response(200, &completion_json("hello world")),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    let rate_limit = resp.rate_limit().unwrap();
+    assert_eq!(rate_limit.limit_requests, Some(100));
+    assert_eq!(rate_limit.remaining_requests, Some(99));
+    assert_eq!(rate_limit.remaining_tokens, Some(5000));
+    assert_eq!(rate_limit.reset_tokens.as_deref(), Some("6m0s"));
+    assert_eq!(rate_limit.limit_tokens, None);
+
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert_eq!(resp.rate_limit().unwrap().remaining_requests, Some(7));
+
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    assert!(resp.rate_limit().is_none());
+}
+
+#[test]
+fn test_user() {
+    let config = chat::ChatConfig {
+        user: Some("user-123".to_string()),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    for provider in [Provider::OpenAI, Provider::Anthropic] {
+        let key = Key::new(provider.clone(), "foo");
+        let (_, _, body) =
+            chat::build_chat_request(&provider, &key, &config, "foo", &messages).unwrap();
+        if provider == Provider::Anthropic {
+            assert_eq!(body["metadata"]["user_id"], "user-123");
+            assert!(body.get("user").is_none());
+        } else {
+            assert_eq!(body["user"], "user-123");
+        }
+    }
+}
+
+#[test]
+fn test_chat_config_builder() {
+    let config = chat::ChatConfig::builder()
+        .temperature(0.0)
+        .max_tokens(100)
+        .seed(42)
+        .other("foo", serde_json::json!("bar"))
+        .build();
+    assert_eq!(config.temperature, Some(0.0));
+    assert_eq!(config.max_tokens, Some(100));
+    assert_eq!(config.seed, Some(42));
+    assert_eq!(config.other.unwrap()["foo"], "bar");
+    assert_eq!(config.top_p, None);
+}
+
+#[test]
+fn test_message_name() {
+    let messages = vec![
+        Message::user("Hi").with_name("alice"),
+        Message::assistant("Hello"),
+    ];
+    let config = chat::ChatConfig::default();
+    for provider in [Provider::OpenAI, Provider::Anthropic] {
+        let key = Key::new(provider.clone(), "foo");
+        let (_, _, body) =
+            chat::build_chat_request(&provider, &key, &config, "model", &messages).unwrap();
+        let first = &body["messages"][0];
+        if provider == Provider::Anthropic {
+            assert!(first.get("name").is_none());
+        } else {
+            assert_eq!(first["name"], "alice");
+            assert!(body["messages"][1].get("name").is_none());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_chat_completion_batch() {
+    let responses = vec![
+        common::response(200, &completion_json("one")),
+        common::response(400, r#"{"error": {"message": "bad request"}}"#),
+        common::response(200, &completion_json("three")),
+    ];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let prompts = vec![
+        vec![Message::user("1")],
+        vec![Message::user("2")],
+        vec![Message::user("3")],
+    ];
+    let results = chat::chat_completion_batch(&provider, &key, "foo", &prompts, 1).await;
+    assert_eq!(results.len(), 3);
+    let content = |i: usize| {
+        let resp = results[i].as_ref().unwrap().structured().unwrap();
+        resp.choices[0].message.content.to_string()
+    };
+    assert_eq!(content(0), "one");
+    assert!(results[1].as_ref().unwrap().structured().is_err());
+    assert_eq!(content(2), "three");
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    let responses = (0..4)
+        .map(|_| common::response(200, &completion_json("hello")))
+        .collect();
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let prompts = vec![hello_messages(); 4];
+    let results = chat::chat_completion_batch(&provider, &key, "foo", &prompts, 2).await;
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert_eq!(requests.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn test_first_text() {
+    let empty = serde_json::json!({
+        "id": "1",
+        "object": "chat.completion",
+        "created": 0,
+        "model": "foo",
+        "choices": []
+    });
+    let responses = vec![
+        common::response(200, &completion_json("hello world")),
+        common::response(200, &empty.to_string()),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.first_text().unwrap(), "hello world");
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert!(matches!(resp.first_text(), Err(Error::EmptyResponse)));
+}
+
+#[test]
+fn test_extra_headers() {
+    let provider = Provider::OpenAI;
+    let key = Key::new(provider.clone(), "foo");
+    let config = chat::ChatConfig::builder()
+        .extra_header("helicone-auth", "Bearer bar")
+        .build();
+    let messages = hello_messages();
+    let (_, headers, _) =
+        chat::build_chat_request(&provider, &key, &config, "gpt-4o-mini", &messages).unwrap();
+    assert_eq!(headers["helicone-auth"], "Bearer bar");
+    assert_eq!(headers["authorization"], "Bearer foo");
+
+    let config = chat::ChatConfig::builder()
+        .extra_header("invalid name", "bar")
+        .build();
+    let result = chat::build_chat_request(&provider, &key, &config, "gpt-4o-mini", &messages);
+    assert!(matches!(result, Err(Error::InvalidHeader(_))));
+}
+
+#[test]
+fn test_chat_model_alias() {
+    let provider = Provider::DeepInfra;
+    let key = Key::new(provider.clone(), "foo");
+    let config = chat::ChatConfig::builder()
+        .model_alias(transformrs::models::ModelAlias::builtin())
+        .build();
+    let messages = hello_messages();
+    let (_, _, body) =
+        chat::build_chat_request(&provider, &key, &config, "llama-3.3-70b", &messages).unwrap();
+    assert_eq!(body["model"], "meta-llama/Llama-3.3-70B-Instruct");
+}
+
+#[tokio::test]
+async fn test_chat_completion_anthropic_cache_control() {
+    let body = serde_json::json!({
+        "id": "msg_1",
+        "type": "message",
+        "role": "assistant",
+        "model": "claude",
+        "content": [{"type": "text", "text": "hello world"}],
+        "stop_reason": "end_turn",
+        "usage": {
+            "input_tokens": 3,
+            "output_tokens": 2,
+            "cache_creation_input_tokens": 0,
+            "cache_read_input_tokens": 1024
+        }
+    });
+    let responses = vec![common::response(200, &body.to_string())];
+    let (address, key, requests) = common::serve_key(Provider::Anthropic, responses);
+    let config = chat::ChatConfig::builder().base_url(&address).build();
+    let messages = vec![
+        Message::system("You are a helpful assistant.").with_cache_control(),
+        Message::user("Hi").with_cache_control(),
+    ];
+    let resp =
+        chat::chat_completion_with_config(&Provider::Anthropic, &key, &config, "claude", &messages)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    let ephemeral = serde_json::json!({"type": "ephemeral"});
+    assert_eq!(body["system"][0]["text"], "You are a helpful assistant.");
+    assert_eq!(body["system"][0]["cache_control"], ephemeral);
+    assert_eq!(
+        body["messages"][0]["content"][0]["cache_control"],
+        ephemeral
+    );
+
+    let usage = resp.usage.unwrap();
+    assert_eq!(usage.cache_creation_input_tokens, Some(0));
+    assert_eq!(usage.cache_read_input_tokens, Some(1024));
+
+    let openai = chat::build_chat_request(&Provider::OpenAI, &key, &config, "foo", &messages)
+        .unwrap()
+        .2;
+    assert!(openai["messages"][0].get("cache_control").is_none());
+}
+
+struct MockBackend;
+
+impl chat::ChatBackend for MockBackend {
+    async fn chat_completion(
+        &self,
+        _config: &chat::ChatConfig,
+        model: &str,
+        _messages: &[Message],
+    ) -> Result<chat::ChatCompletion, Error> {
+        let json = completion_json(&format!("mock {model}"));
+        Ok(serde_json::from_str(&json).unwrap())
+    }
+}
+
+async fn reply(backend: &impl chat::ChatBackend) -> String {
+    let config = chat::ChatConfig::default();
+    let resp = backend
+        .chat_completion(&config, "foo", &hello_messages())
+        .await
+        .unwrap();
+    resp.first_text().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_chat_backend() {
+    assert_eq!(reply(&MockBackend).await, "mock foo");
+
+    let responses = vec![common::response(200, &completion_json("hello world"))];
+    let (address, _requests) = common::serve(responses);
+    let (_provider, key) = local_key(&address);
+    let backend = transformrs::HttpBackend::new(key);
+    assert_eq!(reply(&backend).await, "hello world");
+}
+
+#[tokio::test]
+async fn test_chat_completion_model() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("a")).unwrap();
+    json["model"] = serde_json::json!("foo-2024-01-01");
+    let mut without = json.clone();
+    without.as_object_mut().unwrap().remove("model");
+    let responses = vec![
+        common::response(200, &json.to_string()),
+        common::response(200, &without.to_string()),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.model(), Some("foo-2024-01-01"));
+    let resp = chat::chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(resp.model(), None);
+}
+
+#[test]
+fn test_logit_bias() {
+    let config = chat::ChatConfig::builder()
+        .logit_bias(9642, 100.0)
+        .logit_bias(2822, -100.0)
+        .build();
+    let messages = hello_messages();
+    let provider = Provider::OpenAI;
+    let key = Key::new(provider.clone(), "foo");
+    let (_, _, body) =
+        chat::build_chat_request(&provider, &key, &config, "gpt-4o-mini", &messages).unwrap();
+    assert_eq!(body["logit_bias"]["9642"], 100.0);
+    assert_eq!(body["logit_bias"]["2822"], -100.0);
+
+    let provider = Provider::Anthropic;
+    let result = chat::build_chat_request(&provider, &key, &config, "claude", &messages);
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
+
+#[test]
+fn test_anonymous_key() {
+    let provider = Provider::Other("http://localhost:8000/v1".to_string());
+    assert!(!provider.requires_key());
+    assert!(Provider::OpenAI.requires_key());
+    let key = Key::new(provider.clone(), "");
+    let config = chat::ChatConfig::default();
+    let messages = hello_messages();
+    let (_, headers, _) =
+        chat::build_chat_request(&provider, &key, &config, "llama", &messages).unwrap();
+    assert!(!headers.contains_key("authorization"));
+
+    let (_, key) = local_key("http://localhost:8000/v1");
+    let (_, headers, _) =
+        chat::build_chat_request(&provider, &key, &config, "llama", &messages).unwrap();
+    assert_eq!(headers["authorization"], "Bearer foo");
+}
+
+#[tokio::test]
+async fn test_request_span() {
+    let capture = common::Capture::default();
+    let writer = capture.clone();
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    // The test runtime is single-threaded, so the default applies to the request.
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let responses = vec![common::response(200, &completion_json("a"))];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    chat::chat_completion(&provider, &key, "foo", &hello_messages())
+        .await
+        .unwrap();
+    let output = capture.output();
+    let line = output
+        .lines()
+        .find(|line| line.contains("Received response"))
+        .unwrap();
+    assert!(line.contains("capability=\"chat\""));
+    assert!(line.contains("model=\"foo\""));
+    assert!(line.contains("status=200"));
+    assert!(line.contains("latency_ms="));
+}
+
+#[tokio::test]
+async fn test_collect_stream() {
+    let tool_call = |index: u64, id: Option<&str>, name: Option<&str>, arguments: &str| {
+        let mut json = chunk_json("");
+        json["choices"][0]["delta"] = serde_json::json!({
+            "tool_calls": [{
+                "index": index,
+                "id": id,
+                "function": {"name": name, "arguments": arguments}
+            }]
+        });
+        json
+    };
+    let mut last = chunk_json("");
+    last["choices"][0]["finish_reason"] = serde_json::json!("tool_calls");
+    last["usage"] = serde_json::json!({
+        "prompt_tokens": 1, "completion_tokens": 2, "total_tokens": 3
+    });
+    let events = [
+        chunk_json("Checking "),
+        chunk_json("the weather."),
+        tool_call(0, Some("call_1"), Some("get_weather"), ""),
+        tool_call(0, None, None, "{\"location\":"),
+        tool_call(1, Some("call_2"), Some("get_time"), "{}"),
+        tool_call(0, None, None, " \"Paris\"}"),
+        last,
+    ];
+    let responses = vec![sse_response(&events), sse_response(&events)];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig::default();
+    let messages = hello_messages();
+    let stream = chat::stream_chat_completion_events(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap();
+    let completion = chat::collect_stream(stream).await.unwrap();
+    assert_eq!(completion.model, "foo");
+    assert_eq!(completion.first_text().unwrap(), "Checking the weather.");
+    let choice = &completion.choices[0];
+    assert_eq!(choice.reason(), Some(chat::FinishReason::ToolCalls));
+    let tool_calls = choice.message.tool_calls.as_ref().unwrap();
+    assert_eq!(tool_calls.len(), 2);
+    assert_eq!(tool_calls[0].id, "call_1");
+    assert_eq!(tool_calls[0].name, "get_weather");
+    assert_eq!(tool_calls[0].arguments["location"], "Paris");
+    assert_eq!(tool_calls[1].name, "get_time");
+    assert_eq!(completion.usage.unwrap().total_tokens, 3);
+
+    // Without the usage.
+    let stream = chat::stream_chat_completion(&provider, &key, "foo", &messages)
+        .await
+        .unwrap();
+    let completion = chat::collect_stream(stream).await.unwrap();
+    assert_eq!(
+        completion.choices[0]
+            .message
+            .tool_calls
+            .as_ref()
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(completion.usage.is_none());
+
+    let empty = futures_util::stream::empty::<chat::ChatCompletionChunk>();
+    let err = chat::collect_stream(empty).await.err().unwrap();
+    assert!(matches!(err, Error::EmptyResponse));
+}
+
+#[tokio::test]
+async fn test_collect_stream_invalid_arguments() {
+    let mut json = chunk_json("");
+    // For example, when the response was cut off at `max_tokens`.
+    json["choices"][0]["delta"] = serde_json::json!({
+        "tool_calls": [{
+            "index": 0,
+            "id": "call_1",
+            "function": {"name": "get_weather", "arguments": "{\"city\": \"Par"}
+        }]
+    });
+    let chunk = serde_json::from_value::<chat::ChatCompletionChunk>(json).unwrap();
+    let stream = futures_util::stream::iter([chunk]);
+    let completion = chat::collect_stream(stream).await.unwrap();
+    let tool_calls = completion.choices[0].message.tool_calls.clone().unwrap();
+    assert_eq!(
+        tool_calls[0].arguments,
+        serde_json::json!(r#"{"city": "Par"#)
+    );
+}
+
+#[tokio::test]
+async fn test_collect_stream_anthropic() {
+    let events = [
+        serde_json::json!({"type": "message_start", "message": {"id": "msg_1", "model": "claude-3-5-haiku-20241022", "usage": {"input_tokens": 3}}}),
+        serde_json::json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+        serde_json::json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}}}),
+        serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"location\": "}}),
+        serde_json::json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}),
+        serde_json::json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 5}}),
+    ];
+    let (address, key, _requests) =
+        common::serve_key(Provider::Anthropic, vec![sse_response(&events)]);
+    let config = chat::ChatConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let messages = hello_messages();
+    let stream = chat::stream_chat_completion_events(
+        &Provider::Anthropic,
+        &key,
+        &config,
+        "claude-3-5-haiku-latest",
+        &messages,
+    )
+    .await
+    .unwrap();
+    let completion = chat::collect_stream(stream).await.unwrap();
+    assert_eq!(completion.id.as_deref(), Some("msg_1"));
+    assert_eq!(completion.model, "claude-3-5-haiku-20241022");
+    assert_eq!(completion.first_text().unwrap(), "Hi");
+    let tool_calls = completion.choices[0].message.tool_calls.clone().unwrap();
+    assert_eq!(tool_calls[0].id, "toolu_1");
+    assert_eq!(tool_calls[0].arguments["location"], "Paris");
+    assert_eq!(completion.usage.unwrap().completion_tokens, 5);
+}
+
+#[tokio::test]
+async fn test_chat_completion_json() {
+    #[derive(serde::Deserialize)]
+    struct Answer {
+        city: String,
+        population: u64,
+    }
+    let responses = vec![
+        common::response(
+            200,
+            &completion_json(r#"{"city": "Paris", "population": 2}"#),
+        ),
+        common::response(200, &completion_json("Paris has 2 million inhabitants.")),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig::builder()
+        .response_format(chat::ResponseFormat::JsonObject)
+        .build();
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let answer = resp.json::<Answer>().unwrap();
+    assert_eq!(answer.city, "Paris");
+    assert_eq!(answer.population, 2);
+
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let err = resp.json::<Answer>().err().unwrap();
+    assert!(matches!(err, Error::Decode(_)));
+    assert!(err.to_string().contains("Paris has 2 million inhabitants."));
+}
+
+#[tokio::test]
+async fn test_reasoning_format() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("42")).unwrap();
+    json["choices"][0]["message"]["reasoning"] = serde_json::json!("6 times 7 is 42.");
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig::builder()
+        .reasoning_format("parsed")
+        .build();
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let message = &resp.choices[0].message;
+    assert_eq!(message.reasoning.as_deref(), Some("6 times 7 is 42."));
+    assert_eq!(resp.first_text().unwrap(), "42");
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["reasoning_format"], "parsed");
+
+    // The reasoning is not sent back.
+    let message = serde_json::to_value(message).unwrap();
+    assert!(message.get("reasoning").is_none());
+
+    let key = Key {
+        provider: Provider::OpenAI,
+        ..key
+    };
+    let result = chat::build_chat_request(&Provider::OpenAI, &key, &config, "gpt-4o", &messages);
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_chat_completion_audio() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("")).unwrap();
+    json["choices"][0]["message"]["content"] = serde_json::Value::Null;
+    json["choices"][0]["message"]["audio"] = serde_json::json!({
+        "id": "audio_1",
+        "data": "UklGRg==",
+        "expires_at": 1700000000,
+        "transcript": "hello world"
+    });
+    let responses = vec![common::response(200, &json.to_string())];
+    let (address, requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let config = chat::ChatConfig::builder()
+        .modalities(&["text", "audio"])
+        .audio("alloy", "wav")
+        .build();
+    let messages = hello_messages();
+    let resp = chat::chat_completion_with_config(&provider, &key, &config, "foo", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let audio = resp.choices[0].message.audio.as_ref().unwrap();
+    assert_eq!(&audio.bytes().unwrap()[..], b"RIFF");
+    assert_eq!(audio.transcript.as_deref(), Some("hello world"));
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["modalities"], serde_json::json!(["text", "audio"]));
+    assert_eq!(
+        body["audio"],
+        serde_json::json!({"voice": "alloy", "format": "wav"})
+    );
+
+    // Only the id is sent back in later turns.
+    let message = serde_json::to_value(&resp.choices[0].message).unwrap();
+    assert_eq!(message["audio"], serde_json::json!({"id": "audio_1"}));
+
+    let key = Key {
+        provider: Provider::Groq,
+        ..key
+    };
+    let result = chat::build_chat_request(&Provider::Groq, &key, &config, "foo", &messages);
+    assert!(matches!(result, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_reasoning_content() {
+    let mut json = serde_json::from_str::<serde_json::Value>(&completion_json("42")).unwrap();
+    json["choices"][0]["message"]["reasoning_content"] = serde_json::json!("6 times 7.");
+    let reasoning = |text: &str| {
+        let mut json = chunk_json("");
+        json["choices"][0]["delta"] = serde_json::json!({"reasoning_content": text});
+        json
+    };
+    let events = [reasoning("6 times "), reasoning("7."), chunk_json("42")];
+    let responses = vec![
+        common::response(200, &json.to_string()),
+        sse_response(&events),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let (provider, key) = local_key(&address);
+    let messages = hello_messages();
+    let resp = chat::chat_completion(&provider, &key, "deepseek-reasoner", &messages)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(
+        resp.choices[0].message.reasoning.as_deref(),
+        Some("6 times 7.")
+    );
+
+    let stream = chat::stream_chat_completion(&provider, &key, "deepseek-reasoner", &messages)
+        .await
+        .unwrap();
+    let resp = chat::collect_stream(stream).await.unwrap();
+    assert_eq!(
+        resp.choices[0].message.reasoning.as_deref(),
+        Some("6 times 7.")
+    );
+    assert_eq!(resp.first_text().unwrap(), "42");
+}
+
+#[test]
+fn test_reasoning_both_keys() {
+    use transformrs::chat::Delta;
+
+    let json = r#"{
+        "role": "assistant",
+        "content": "42",
+        "reasoning": "6 times 7.",
+        "reasoning_content": "6 times 7."
+    }"#;
+    let message = serde_json::from_str::<Message>(json).unwrap();
+    assert_eq!(message.reasoning.as_deref(), Some("6 times 7."));
+    let value = serde_json::to_value(&message).unwrap();
+    assert!(value.get("reasoning").is_none());
+
+    let json = r#"{"content": null, "reasoning": "6 ", "reasoning_content": "6 "}"#;
+    let delta = serde_json::from_str::<Delta>(json).unwrap();
+    assert_eq!(delta.reasoning.as_deref(), Some("6 "));
+    let value = serde_json::to_value(&delta).unwrap();
+    assert_eq!(value["reasoning"], "6 ");
+}
+
+#[test]
+fn test_api_version_and_beta_features() {
+    let messages = hello_messages();
+    let config = chat::ChatConfig::default();
+    let key = Key::new(Provider::Anthropic, "foo");
+    let (_, headers, _) =
+        chat::build_chat_request(&Provider::Anthropic, &key, &config, "claude", &messages).unwrap();
+    assert_eq!(headers["anthropic-version"], transformrs::ANTHROPIC_VERSION);
+    assert!(!headers.contains_key("anthropic-beta"));
+
+    let key = Key {
+        api_version: Some("2024-01-01".to_string()),
+        beta_features: Some(vec!["a-2024".to_string(), "b-2025".to_string()]),
+        ..key
+    };
+    let (_, headers, _) =
+        chat::build_chat_request(&Provider::Anthropic, &key, &config, "claude", &messages).unwrap();
+    assert_eq!(headers["anthropic-version"], "2024-01-01");
+    assert_eq!(headers["anthropic-beta"], "a-2024,b-2025");
+
+    let key = Key {
+        provider: Provider::OpenAI,
+        beta_features: Some(vec!["assistants=v2".to_string()]),
+        ..key
+    };
+    let (_, headers, _) =
+        chat::build_chat_request(&Provider::OpenAI, &key, &config, "gpt-4o", &messages).unwrap();
+    assert_eq!(headers["openai-beta"], "assistants=v2");
+
+    let key = Key {
+        provider: Provider::Azure,
+        api_version: Some("2025-01-01-preview".to_string()),
+        ..key
+    };
+    let config = chat::ChatConfig {
+        base_url: Some("https://foo.openai.azure.com".to_string()),
+        ..Default::default()
+    };
+    let (address, headers, _) =
+        chat::build_chat_request(&Provider::Azure, &key, &config, "gpt-4o", &messages).unwrap();
+    assert!(address
+        .as_str()
+        .ends_with("/chat/completions?api-version=2025-01-01-preview"));
+    assert!(!headers.contains_key("openai-beta"));
+}
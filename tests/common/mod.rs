@@ -1,4 +1,14 @@
+#![allow(dead_code)]
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
 use tracing::subscriber::SetGlobalDefaultError;
+use transformrs::Key;
+use transformrs::Provider;
 
 /// Initialize logging with the given level.
 pub fn init_subscriber(level: tracing::Level) -> Result<(), SetGlobalDefaultError> {
@@ -21,3 +31,93 @@ pub fn init_tracing() {
         Err(_e) => (),
     }
 }
+
+/// Serve the given HTTP responses, in order, on a local port.
+///
+/// Returns the base URL of the server and the requests that it received.
+/// This allows testing behavior that does not depend on a real provider.
+pub fn serve(responses: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let received = requests.clone();
+    std::thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_request(&mut stream);
+            received.lock().unwrap().push(request);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (address, requests)
+}
+
+/// Serve the given HTTP responses, see `serve`, and create a key for the
+/// provider.
+///
+/// Returns the base URL to set in the config, the key, and the requests that
+/// the server received.
+pub fn serve_key(
+    provider: Provider,
+    responses: Vec<String>,
+) -> (String, Key, Arc<Mutex<Vec<String>>>) {
+    let (address, requests) = serve(responses);
+    (address, Key::new(provider, "foo"), requests)
+}
+
+/// Create a raw HTTP response with the given status and JSON body.
+pub fn response(status: u16, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = vec![];
+    let mut buffer = [0; 4096];
+    loop {
+        let n = stream.read(&mut buffer).unwrap();
+        request.extend_from_slice(&buffer[..n]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some(end) = text.find("\r\n\r\n") {
+            let content_length = text[..end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    if name.eq_ignore_ascii_case("content-length") {
+                        value.trim().parse::<usize>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or(0);
+            if request.len() >= end + 4 + content_length {
+                return text;
+            }
+        }
+        if n == 0 {
+            return text;
+        }
+    }
+}
+
+/// Writer that keeps the log output, so that tests can inspect it.
+#[derive(Clone, Default)]
+pub struct Capture(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Capture {
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+}
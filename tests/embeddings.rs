@@ -0,0 +1,152 @@
+extern crate transformrs;
+
+mod common;
+
+use transformrs::embeddings::cosine_similarity;
+use transformrs::embeddings::EmbedConfig;
+use transformrs::embeddings::Embeddings;
+use transformrs::Error;
+use transformrs::Provider;
+
+fn inputs() -> Vec<String> {
+    vec![
+        "The cat sits on the mat.".to_string(),
+        "A cat is sitting on a mat.".to_string(),
+        "Stock markets fell sharply today.".to_string(),
+    ]
+}
+
+async fn embed_helper(provider: Provider, model: &str) -> Result<Embeddings, Error> {
+    common::init_tracing();
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&provider).unwrap();
+    let resp = transformrs::embeddings::embed(&provider, &key, model, &inputs()).await?;
+    resp.structured()
+}
+
+fn check_similarity(embeddings: &Embeddings) {
+    assert_eq!(embeddings.embeddings.len(), 3);
+    let vectors: Vec<&Vec<f32>> = embeddings.embeddings.iter().map(|e| &e.vector).collect();
+    let similar = cosine_similarity(vectors[0], vectors[1]).unwrap();
+    let different = cosine_similarity(vectors[0], vectors[2]).unwrap();
+    assert!(similar > different);
+}
+
+#[tokio::test]
+async fn test_embed_deepinfra() {
+    let embeddings = embed_helper(Provider::DeepInfra, "BAAI/bge-base-en-v1.5")
+        .await
+        .unwrap();
+    check_similarity(&embeddings);
+}
+
+#[tokio::test]
+async fn test_embed_openai() {
+    let embeddings = embed_helper(Provider::OpenAI, "text-embedding-3-small")
+        .await
+        .unwrap();
+    check_similarity(&embeddings);
+    assert!(embeddings.usage.is_some());
+}
+
+#[tokio::test]
+async fn test_embed_batch_deepinfra() {
+    common::init_tracing();
+    let provider = Provider::DeepInfra;
+    let key = transformrs::load_keys(".env")
+        .for_provider(&provider)
+        .unwrap();
+    let config = EmbedConfig::default();
+    let model = "BAAI/bge-base-en-v1.5";
+    let embeddings =
+        transformrs::embeddings::embed_batch(&provider, &key, &config, model, &inputs(), 2)
+            .await
+            .unwrap();
+    let indexes: Vec<u64> = embeddings.embeddings.iter().map(|e| e.index).collect();
+    assert_eq!(indexes, vec![0, 1, 2]);
+    check_similarity(&embeddings);
+}
+
+#[test]
+fn test_cosine_similarity() {
+    let similarity = |a: &[f32], b: &[f32]| cosine_similarity(a, b).unwrap();
+    assert!((similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+    assert!(similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+    assert!((similarity(&[1.0, 0.0], &[-1.0, 0.0]) + 1.0).abs() < 1e-6);
+    assert_eq!(similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0, 0.0, 0.0]), None);
+}
+
+#[tokio::test]
+async fn test_embed_api_error() {
+    let responses = vec![
+        common::response(401, r#"{"error": {"message": "invalid key"}}"#),
+        common::response(422, r#"{"detail": "model not found"}"#),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let provider = Provider::Other(address);
+    let key = transformrs::Key::new(provider.clone(), "foo");
+    let config = EmbedConfig::default();
+    let input = vec!["Hi".to_string()];
+    for expected in ["invalid key", "model not found"] {
+        let err = transformrs::embeddings::embed_with_config(&provider, &key, &config, "m", &input)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap_err();
+        match err {
+            transformrs::Error::Api { message, .. } => assert_eq!(message, expected),
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_embed_nebius_novita_address() {
+    let body = serde_json::json!({
+        "data": [{"index": 0, "embedding": [1.0, 0.0]}]
+    });
+    for (provider, path) in [
+        (Provider::Nebius, "/v1/embeddings"),
+        (Provider::Novita, "/v3/openai/embeddings"),
+    ] {
+        let responses = vec![common::response(200, &body.to_string())];
+        let (address, key, requests) = common::serve_key(provider.clone(), responses);
+        let config = EmbedConfig {
+            base_url: Some(address),
+            ..Default::default()
+        };
+        let input = vec!["Hi".to_string()];
+        transformrs::embeddings::embed_with_config(&provider, &key, &config, "bge", &input)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap();
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with(&format!("POST {path} ")));
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+        assert_eq!(body["input"][0], "Hi");
+        assert_eq!(body["model"], "bge");
+    }
+}
+
+/// Skipped when no key is set for the provider.
+async fn embed_if_key(provider: Provider, model: &str) {
+    let keys = transformrs::load_keys(".env");
+    if keys.for_provider(&provider).is_none() {
+        return;
+    }
+    let embeddings = embed_helper(provider, model).await.unwrap();
+    check_similarity(&embeddings);
+}
+
+#[tokio::test]
+async fn test_embed_nebius() {
+    embed_if_key(Provider::Nebius, "BAAI/bge-en-icl").await;
+}
+
+#[tokio::test]
+async fn test_embed_novita() {
+    embed_if_key(Provider::Novita, "baai/bge-m3").await;
+}
@@ -0,0 +1,151 @@
+extern crate transformrs;
+
+mod common;
+
+use transformrs::image_generation::ImageData;
+use transformrs::image_generation::ImageFormat;
+use transformrs::image_generation::ImageGenConfig;
+use transformrs::Error;
+use transformrs::Key;
+use transformrs::Provider;
+
+#[tokio::test]
+async fn test_generate_openai() {
+    common::init_tracing();
+    let provider = Provider::OpenAI;
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&provider).unwrap();
+    let config = ImageGenConfig {
+        size: Some("256x256".to_string()),
+        response_format: Some(ImageFormat::B64Json),
+        ..Default::default()
+    };
+    let prompt = "A beautiful sunset over a calm ocean.";
+    let images =
+        transformrs::image_generation::generate(&provider, &key, &config, "dall-e-2", prompt)
+            .await
+            .unwrap()
+            .structured()
+            .unwrap();
+    assert_eq!(images.images.len(), 1);
+    assert!(matches!(images.images[0].data, ImageData::Bytes(_)));
+}
+
+#[tokio::test]
+async fn test_generate_local() {
+    let body = serde_json::json!({
+        "created": 1,
+        "data": [
+            {"url": "https://example.com/a.png"},
+            {"b64_json": "iVBORw==", "revised_prompt": "a sunset"}
+        ]
+    });
+    let responses = vec![common::response(200, &body.to_string())];
+    let (address, requests) = common::serve(responses);
+    let provider = Provider::Other(address);
+    let key = Key::new(provider.clone(), "foo");
+    let config = ImageGenConfig {
+        n: Some(2),
+        ..Default::default()
+    };
+    let images = transformrs::image_generation::generate(&provider, &key, &config, "foo", "sunset")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /images/generations"));
+    assert_eq!(images.created, Some(1));
+    assert_eq!(
+        images.images[0].data,
+        ImageData::Url("https://example.com/a.png".to_string())
+    );
+    assert_eq!(
+        images.images[1].data,
+        ImageData::Bytes(bytes::Bytes::from_static(b"\x89PNG"))
+    );
+    assert_eq!(images.images[1].revised_prompt.as_deref(), Some("a sunset"));
+}
+
+#[tokio::test]
+async fn test_generate_unsupported_provider() {
+    let provider = Provider::Anthropic;
+    let key = Key::new(provider.clone(), "foo");
+    let config = ImageGenConfig::default();
+    let resp =
+        transformrs::image_generation::generate(&provider, &key, &config, "foo", "sunset").await;
+    assert!(matches!(
+        resp,
+        Err(Error::UnsupportedProvider(Provider::Anthropic))
+    ));
+}
+
+#[tokio::test]
+async fn test_generate_fireworks() {
+    let body = r#"[{"base64": "iVBORw==", "finish_reason": "SUCCESS", "seed": 1}]"#;
+    let responses = vec![common::response(200, body)];
+    let (address, requests) = common::serve(responses);
+    let provider = Provider::Fireworks;
+    let key = Key::new(provider.clone(), "foo");
+    let config = ImageGenConfig {
+        size: Some("1024x768".to_string()),
+        n: Some(1),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let model = "accounts/fireworks/models/stable-diffusion-xl-1024-v1-0";
+    let images = transformrs::image_generation::generate(&provider, &key, &config, model, "sunset")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(
+        images.images[0].data,
+        ImageData::Bytes(bytes::Bytes::from_static(b"\x89PNG"))
+    );
+    let request = requests.lock().unwrap()[0].clone();
+    let path = format!("POST /inference/v1/image_generation/{model} ");
+    assert!(request.starts_with(&path));
+    assert!(request.to_lowercase().contains("accept: application/json"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["width"], 1024);
+    assert_eq!(body["height"], 768);
+    assert_eq!(body["samples"], 1);
+    assert!(body.get("model").is_none());
+}
+
+#[tokio::test]
+async fn test_generate_together() {
+    let body = r#"{"data": [{"b64_json": "iVBORw=="}]}"#;
+    let responses = vec![common::response(200, body)];
+    let (address, requests) = common::serve(responses);
+    let provider = Provider::TogetherAI;
+    let key = Key::new(provider.clone(), "foo");
+    let config = ImageGenConfig {
+        size: Some("512x512".to_string()),
+        response_format: Some(ImageFormat::B64Json),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let model = "black-forest-labs/FLUX.1-schnell";
+    transformrs::image_generation::generate(&provider, &key, &config, model, "sunset")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/images/generations"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["response_format"], "base64");
+    assert_eq!(body["width"], 512);
+    assert!(body.get("size").is_none());
+
+    let provider = Provider::Groq;
+    let resp = transformrs::image_generation::generate(&provider, &key, &config, model, "").await;
+    assert!(matches!(
+        resp.err(),
+        Some(Error::UnsupportedProvider(Provider::Groq))
+    ));
+}
@@ -0,0 +1,130 @@
+extern crate transformrs;
+
+use transformrs::Capability;
+use transformrs::Error;
+use transformrs::Key;
+use transformrs::Keys;
+use transformrs::Provider;
+
+#[test]
+fn test_try_for_provider() {
+    let keys = Keys {
+        keys: vec![Key::new(Provider::OpenAI, "foo")],
+    };
+    let key = keys.try_for_provider(&Provider::OpenAI).unwrap();
+    assert_eq!(key.key, "foo");
+    assert!(keys.for_provider(&Provider::Groq).is_none());
+    let err = keys.try_for_provider(&Provider::Groq).unwrap_err();
+    assert!(matches!(err, Error::MissingKey(Provider::Groq)));
+    assert!(err.to_string().contains("GROQ_KEY"));
+}
+
+#[test]
+fn test_providers() {
+    let key = |provider: Provider| Key::new(provider, "foo");
+    let keys = Keys {
+        keys: vec![key(Provider::Groq), key(Provider::OpenAI)],
+    };
+    assert_eq!(keys.providers(), vec![Provider::Groq, Provider::OpenAI]);
+    let mut providers = vec![];
+    for key in &keys {
+        providers.push(key.provider.clone());
+    }
+    assert_eq!(providers, keys.providers());
+    assert_eq!(keys.iter().count(), 2);
+}
+
+#[test]
+fn test_keys_insert() {
+    let mut keys = Keys::new();
+    assert!(keys.providers().is_empty());
+    keys.insert(Provider::OpenAI, "foo");
+    keys.insert(Provider::Groq, "bar");
+    keys.insert(Provider::OpenAI, "baz");
+    assert_eq!(keys.providers(), vec![Provider::OpenAI, Provider::Groq]);
+    let key = keys.try_for_provider(&Provider::OpenAI).unwrap();
+    assert_eq!(key.key, "baz");
+    assert_eq!(key.organization, None);
+
+    let key = Key::new(Provider::Anthropic, "qux");
+    assert_eq!(key.provider, Provider::Anthropic);
+    assert_eq!(key.key, "qux");
+}
+
+#[test]
+fn test_load_keys_aws_session_token() {
+    let names = ["AMAZON_KEY", "AWS_ACCESS_KEY_ID", "AWS_SESSION_TOKEN"];
+    if names.iter().any(|name| std::env::var(name).is_ok()) {
+        // The environment takes precedence over the file.
+        return;
+    }
+    let path = std::env::temp_dir().join("transformrs-aws.env");
+    let env = "AWS_ACCESS_KEY_ID=id\nAWS_SECRET_ACCESS_KEY=secret\nAWS_SESSION_TOKEN=token\n";
+    std::fs::write(&path, env).unwrap();
+    let keys = transformrs::load_keys(path.to_str().unwrap());
+    let key = keys.try_for_provider(&Provider::Amazon).unwrap();
+    assert_eq!(key.key, "id:secret:token");
+}
+
+#[test]
+fn test_load_keys_providers() {
+    let providers = [
+        Provider::Cerebras,
+        Provider::Mistral,
+        Provider::SambaNova,
+        Provider::TogetherAI,
+    ];
+    if providers
+        .iter()
+        .any(|provider| std::env::var(provider.key_name()).is_ok())
+    {
+        // The environment takes precedence over the file.
+        return;
+    }
+    let path = std::env::temp_dir().join("transformrs-providers.env");
+    let env = providers
+        .iter()
+        .map(|provider| format!("{}=foo\n", provider.key_name()))
+        .collect::<String>();
+    std::fs::write(&path, env).unwrap();
+    let keys = transformrs::load_keys(path.to_str().unwrap());
+    for provider in providers {
+        assert!(keys.try_for_provider(&provider).is_ok(), "{provider}");
+    }
+}
+
+#[test]
+fn test_provider_from_str() {
+    assert_eq!(
+        "deepinfra".parse::<Provider>().unwrap(),
+        Provider::DeepInfra
+    );
+    assert_eq!("OpenAI".parse::<Provider>().unwrap(), Provider::OpenAI);
+    assert_eq!(
+        "together".parse::<Provider>().unwrap(),
+        Provider::TogetherAI
+    );
+    let url = "http://localhost:8000/v1";
+    assert_eq!(
+        url.parse::<Provider>().unwrap(),
+        Provider::Other(url.to_string())
+    );
+    let err = "foo".parse::<Provider>().unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig(_)));
+    for provider in [Provider::ElevenLabs, Provider::Other(url.to_string())] {
+        assert_eq!(provider.to_string().parse::<Provider>().unwrap(), provider);
+    }
+}
+
+#[test]
+fn test_provider_supports() {
+    assert!(Provider::OpenAI.supports(Capability::Chat));
+    assert!(Provider::OpenAI.supports(Capability::Stt));
+    assert!(Provider::ElevenLabs.supports(Capability::Tts));
+    assert!(!Provider::ElevenLabs.supports(Capability::Chat));
+    assert!(!Provider::Anthropic.supports(Capability::Embeddings));
+    assert!(Provider::Anthropic.supports(Capability::Streaming));
+    let other = Provider::Other("http://localhost:8000/v1".to_string());
+    assert!(other.supports(Capability::Embeddings));
+    assert!(!other.supports(Capability::Tts));
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "mock")]
+extern crate transformrs;
+
+use transformrs::chat::ChatBackend;
+use transformrs::chat::ChatConfig;
+use transformrs::mock::MockBackend;
+use transformrs::text_to_speech::TTSConfig;
+use transformrs::text_to_speech::TtsBackend;
+use transformrs::Message;
+
+#[tokio::test]
+async fn test_mock_chat_completion() {
+    let backend = MockBackend::new();
+    let config = ChatConfig::default();
+    let messages = vec![Message::user("Hi")];
+    let resp = backend
+        .chat_completion(&config, "mock", &messages)
+        .await
+        .unwrap();
+    assert_eq!(resp.first_text().unwrap(), "hello world");
+    assert_eq!(resp.usage.unwrap().total_tokens, 3);
+}
+
+#[tokio::test]
+async fn test_mock_tts() {
+    let backend = MockBackend::new();
+    let config = TTSConfig::default();
+    let speech = backend.tts(&config, None, "Hi").await.unwrap();
+    assert_eq!(speech.file_format, "mp3");
+    assert_eq!(&speech.audio[..2], &[0xFF, 0xFB]);
+}
+
+/// Application code that is written against the traits.
+async fn greet<B: ChatBackend + TtsBackend>(backend: &B) -> (String, usize) {
+    let messages = vec![Message::user("Say hello.")];
+    let resp = backend
+        .chat_completion(&ChatConfig::default(), "mock", &messages)
+        .await
+        .unwrap();
+    let text = resp.first_text().unwrap().to_string();
+    let speech = backend
+        .tts(&TTSConfig::default(), None, &text)
+        .await
+        .unwrap();
+    (text, speech.audio.len())
+}
+
+#[tokio::test]
+async fn test_mock_generic() {
+    let (text, len) = greet(&MockBackend).await;
+    assert_eq!(text, "hello world");
+    assert!(0 < len);
+}
@@ -1,11 +1,17 @@
 extern crate transformrs;
 
-use std::error::Error;
+mod common;
+
+use std::time::Duration;
 use transformrs::models::models;
+use transformrs::models::ModelAlias;
 use transformrs::models::Models;
+use transformrs::Error;
+use transformrs::Key;
+use transformrs::Keys;
 use transformrs::Provider;
 
-async fn test_models(provider: Provider) -> Result<Models, Box<dyn Error + Send + Sync>> {
+async fn test_models(provider: Provider) -> Result<Models, Error> {
     let keys = transformrs::load_keys(".env");
     let key = keys.for_provider(&provider).unwrap();
     let resp = models(&provider, &key).await;
@@ -31,3 +37,104 @@ async fn test_models_openai() {
     let models = test_models(Provider::OpenAI).await.unwrap();
     assert!(models.contains("gpt-4o"));
 }
+
+#[tokio::test]
+async fn test_list_models_deepinfra() {
+    let provider = Provider::DeepInfra;
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&provider).unwrap();
+    let models = transformrs::models::list_models(&provider, &key)
+        .await
+        .unwrap();
+    assert!(models
+        .iter()
+        .any(|m| m.id == "meta-llama/Llama-3.3-70B-Instruct"));
+}
+
+#[tokio::test]
+async fn test_list_models_unsupported_provider() {
+    let provider = Provider::Amazon;
+    let key = transformrs::Key::new(provider.clone(), "foo");
+    let resp = transformrs::models::list_models(&provider, &key).await;
+    assert!(matches!(
+        resp,
+        Err(Error::UnsupportedProvider(Provider::Amazon))
+    ));
+}
+
+#[tokio::test]
+async fn test_key_validate() {
+    let responses = vec![
+        common::response(200, r#"{"object": "list", "data": []}"#),
+        common::response(401, r#"{"error": {"message": "invalid key"}}"#),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let key = Key::new(Provider::Other(address), "foo");
+    key.validate().await.unwrap();
+    let err = key.validate().await.unwrap_err();
+    assert!(matches!(err, Error::Unauthorized(Provider::Other(_))));
+}
+
+#[tokio::test]
+async fn test_models_raw() {
+    let body = r#"{"object": "list", "data": [{"id": "foo"}], "extra": 1}"#;
+    let responses = vec![
+        common::response(200, body),
+        common::response(502, "<html>Bad Gateway</html>"),
+    ];
+    let (address, _requests) = common::serve(responses);
+    let provider = Provider::Other(address);
+    let key = Key::new(provider.clone(), "foo");
+    let resp = models(&provider, &key).await.unwrap();
+    assert_eq!(resp.raw_value().unwrap()["extra"], 1);
+    assert_eq!(resp.raw()["extra"], 1);
+    assert!(resp.structured().unwrap().contains("foo"));
+
+    let resp = models(&provider, &key).await.unwrap();
+    assert_eq!(resp.bytes().as_ref(), b"<html>Bad Gateway</html>");
+    assert!(resp.raw_value().is_err());
+    let err = resp.structured().unwrap_err();
+    assert!(matches!(err, Error::Api { status: 502, .. }));
+}
+
+#[test]
+fn test_model_alias() {
+    let aliases = ModelAlias::builtin();
+    assert_eq!(
+        aliases.resolve(&Provider::Groq, "llama-3.3-70b"),
+        "llama-3.3-70b-versatile"
+    );
+    assert_eq!(
+        aliases.resolve(&Provider::OpenAI, "llama-3.3-70b"),
+        "llama-3.3-70b"
+    );
+    assert_eq!(aliases.resolve(&Provider::Groq, "unknown"), "unknown");
+
+    let mut aliases = ModelAlias::new();
+    aliases.insert("fast", Provider::OpenAI, "gpt-4o-mini");
+    assert_eq!(aliases.resolve(&Provider::OpenAI, "fast"), "gpt-4o-mini");
+}
+
+#[tokio::test]
+async fn test_keys_health_check() {
+    let ok = common::serve(vec![common::response(200, r#"{"data": []}"#)]).0;
+    let unauthorized = common::serve(vec![common::response(401, "{}")]).0;
+    // Accepts connections without ever responding.
+    let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent = format!("http://{}", silent.local_addr().unwrap());
+    let key = |address: &str| Key::new(Provider::Other(address.to_string()), "foo");
+    let keys = Keys {
+        keys: vec![key(&ok), key(&unauthorized), key(&silent)],
+    };
+    let results = keys.health_check(Duration::from_millis(200)).await;
+    assert_eq!(results.len(), 3);
+    assert!(results[&Provider::Other(ok)].is_ok());
+    assert!(matches!(
+        results[&Provider::Other(unauthorized)],
+        Err(Error::Unauthorized(_))
+    ));
+    assert!(matches!(
+        results[&Provider::Other(silent)],
+        Err(Error::Timeout)
+    ));
+}
@@ -0,0 +1,220 @@
+extern crate transformrs;
+
+mod common;
+
+use bytes::Bytes;
+use futures_util::stream::StreamExt;
+use transformrs::speech_to_text::STTConfig;
+use transformrs::speech_to_text::Transcription;
+use transformrs::text_to_speech::TTSConfig;
+use transformrs::Error;
+use transformrs::Key;
+use transformrs::Provider;
+
+/// Generate audio via text-to-speech so that there is something to transcribe.
+async fn hello_world_audio() -> Bytes {
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&Provider::OpenAI).unwrap();
+    let config = TTSConfig {
+        voice: Some("alloy".to_string()),
+        ..Default::default()
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hello world.")
+        .await
+        .unwrap();
+    resp.structured().unwrap().audio
+}
+
+async fn stt_helper(
+    provider: &Provider,
+    config: &STTConfig,
+    model: Option<&str>,
+) -> Result<Transcription, Error> {
+    common::init_tracing();
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(provider).unwrap();
+    let audio = hello_world_audio().await;
+    let resp = transformrs::speech_to_text::transcribe(&key, config, model, audio).await?;
+    resp.structured()
+}
+
+fn canonicalize(text: &str) -> String {
+    text.to_lowercase()
+        .replace([',', '.', '!'], "")
+        .trim()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_stt_openai() {
+    let config = STTConfig::default();
+    let transcription = stt_helper(&Provider::OpenAI, &config, None).await.unwrap();
+    assert_eq!(canonicalize(&transcription.text), "hello world");
+}
+
+#[tokio::test]
+async fn test_stt_openai_text() {
+    let config = STTConfig {
+        response_format: Some("text".to_string()),
+        ..Default::default()
+    };
+    let transcription = stt_helper(&Provider::OpenAI, &config, None).await.unwrap();
+    assert_eq!(canonicalize(&transcription.text), "hello world");
+}
+
+#[tokio::test]
+async fn test_stt_deepinfra() {
+    let config = STTConfig {
+        language: Some("en".to_string()),
+        ..Default::default()
+    };
+    let transcription = stt_helper(&Provider::DeepInfra, &config, None)
+        .await
+        .unwrap();
+    assert_eq!(canonicalize(&transcription.text), "hello world");
+}
+
+#[tokio::test]
+async fn test_stt_unsupported_provider() {
+    let key = Key::new(Provider::Hyperbolic, "foo");
+    let config = STTConfig::default();
+    let audio = Bytes::from_static(b"foo");
+    let resp = transformrs::speech_to_text::transcribe(&key, &config, None, audio).await;
+    assert!(matches!(
+        resp,
+        Err(Error::UnsupportedProvider(Provider::Hyperbolic))
+    ));
+}
+
+#[tokio::test]
+async fn test_translate_base_url() {
+    let responses = vec![common::response(200, r#"{"text": "Hello world."}"#)];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = STTConfig {
+        language: Some("nl".to_string()),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let audio = Bytes::from_static(b"ID3");
+    let transcription = transformrs::speech_to_text::translate(&key, &config, None, audio)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(transcription.text, "Hello world.");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/audio/translations"));
+    assert!(!request.contains("name=\"language\""));
+}
+
+#[tokio::test]
+async fn test_transcribe_validation() {
+    let key = Key::new(Provider::OpenAI, "foo");
+    let config = STTConfig::default();
+    let audio = Bytes::from_static(b"<html>not audio</html>");
+    let resp = transformrs::speech_to_text::transcribe(&key, &config, None, audio).await;
+    let err = resp.err().unwrap();
+    assert!(err.to_string().contains("unsupported audio format"));
+
+    let max = transformrs::speech_to_text::MAX_AUDIO_BYTES;
+    let mut audio = b"RIFF".to_vec();
+    audio.resize(max + 1, 0);
+    let resp = transformrs::speech_to_text::transcribe(&key, &config, None, audio.into()).await;
+    let err = resp.err().unwrap();
+    assert!(matches!(err, Error::InvalidConfig(_)));
+    assert!(err.to_string().contains("maximum"));
+}
+
+#[tokio::test]
+async fn test_transcribe_stream() {
+    let responses = vec![
+        common::response(200, r#"{"text": "Hello"}"#),
+        common::response(200, r#"{"text": "world."}"#),
+    ];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = STTConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let segments = vec![Bytes::from_static(b"RIFF1"), Bytes::from_static(b"RIFF2")];
+    let audio = futures_util::stream::iter(segments);
+    let mut stream = transformrs::speech_to_text::transcribe_stream(&key, &config, None, audio);
+    let mut texts = vec![];
+    while let Some(transcription) = stream.next().await {
+        texts.push(transcription.unwrap().text);
+    }
+    assert_eq!(texts, vec!["Hello", "world."]);
+    let requests = requests.lock().unwrap();
+    assert!(!requests[0].contains("name=\"prompt\""));
+    assert!(requests[1].contains("name=\"prompt\"\r\n\r\nHello\r\n"));
+}
+
+#[tokio::test]
+async fn test_transcribe_words() {
+    let body = r#"{
+        "text": "Hello world.",
+        "language": "english",
+        "words": [
+            {"word": "Hello", "start": 0.0, "end": 0.4},
+            {"word": "world", "start": 0.5, "end": 0.9}
+        ]
+    }"#;
+    let (address, key, requests) =
+        common::serve_key(Provider::OpenAI, vec![common::response(200, body)]);
+    let config = STTConfig {
+        response_format: Some("verbose_json".to_string()),
+        timestamp_granularities: Some(vec!["word".to_string(), "segment".to_string()]),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let audio = Bytes::from_static(b"RIFF");
+    let transcription = transformrs::speech_to_text::transcribe(&key, &config, None, audio)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    let words = transcription.words.unwrap();
+    assert_eq!(words.len(), 2);
+    assert_eq!(words[1].word, "world");
+    assert_eq!(words[1].start, 0.5);
+    assert_eq!(words[1].end, 0.9);
+    assert!(transcription.segments.is_none());
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains("name=\"timestamp_granularities[]\"\r\n\r\nword\r\n"));
+    assert!(request.contains("name=\"timestamp_granularities[]\"\r\n\r\nsegment\r\n"));
+}
+
+#[tokio::test]
+async fn test_transcribe_file() {
+    let (address, key, requests) = common::serve_key(
+        Provider::OpenAI,
+        vec![common::response(200, r#"{"text": "Hi"}"#)],
+    );
+    let config = STTConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let path = std::env::temp_dir().join("transformrs-transcribe.mp3");
+    std::fs::write(&path, b"ID3").unwrap();
+    let transcription = transformrs::speech_to_text::transcribe_file(&key, &config, None, &path)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(transcription.text, "Hi");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains("filename=\"transformrs-transcribe.mp3\""));
+    assert!(request.contains("Content-Type: audio/mpeg"));
+
+    let missing = std::env::temp_dir().join("transformrs-missing.wav");
+    let resp = transformrs::speech_to_text::transcribe_file(&key, &config, None, missing).await;
+    assert!(matches!(resp.err().unwrap(), Error::Io(_)));
+
+    let resp = transformrs::speech_to_text::transcribe_file(&key, &config, None, "notes.txt").await;
+    assert!(resp
+        .err()
+        .unwrap()
+        .to_string()
+        .contains("unsupported audio format"));
+}
@@ -2,10 +2,10 @@ extern crate transformrs;
 
 mod common;
 
-use std::error::Error;
 use std::fs::File;
 use std::io::Write;
 use transformrs::text_to_image::Images;
+use transformrs::Error;
 use transformrs::Provider;
 
 #[tokio::test]
@@ -13,8 +13,10 @@ async fn text_to_image_hyperbolic() {
     common::init_tracing();
     let keys = transformrs::load_keys(".env");
     let key = keys.for_provider(&Provider::Hyperbolic).expect("no key");
-    let mut config = transformrs::text_to_image::TTIConfig::default();
-    config.model = "FLUX.1-dev".to_string();
+    let config = transformrs::text_to_image::TTIConfig {
+        model: "FLUX.1-dev".to_string(),
+        ..Default::default()
+    };
     let prompt = "A beautiful sunset over a calm ocean.";
     let resp = transformrs::text_to_image::text_to_image(&key, config, prompt)
         .await
@@ -30,12 +32,14 @@ async fn text_to_image_helper(
     provider: Provider,
     model: &str,
     prompt: &str,
-) -> Result<Images, Box<dyn Error + Send + Sync>> {
+) -> Result<Images, Error> {
     common::init_tracing();
     let keys = transformrs::load_keys(".env");
     let key = keys.for_provider(&provider).expect("no key");
-    let mut config = transformrs::text_to_image::TTIConfig::default();
-    config.model = model.to_string();
+    let config = transformrs::text_to_image::TTIConfig {
+        model: model.to_string(),
+        ..Default::default()
+    };
     let resp = transformrs::text_to_image::text_to_image(&key, config, prompt)
         .await
         .unwrap();
@@ -66,3 +70,47 @@ async fn text_to_image_deepinfra_error() {
     .unwrap_err();
     assert!(resp.to_string().contains("Model is not available"));
 }
+
+#[test]
+fn test_base64_decode_invalid() {
+    let image = transformrs::text_to_image::Base64Image {
+        index: 0,
+        random_seed: None,
+        image: "data:image/png;base64,not base64!".to_string(),
+    };
+    let err = image.base64_decode().err().unwrap();
+    assert!(matches!(err, Error::Decode(_)));
+}
+
+#[tokio::test]
+async fn text_to_image_other() {
+    let body = r#"{"images": [{"index": 0, "random_seed": null, "image": "cG5n"}]}"#;
+    let (address, requests) = common::serve(vec![common::response(200, body)]);
+    let key = transformrs::Key::new(Provider::Other(address), "");
+    let config = transformrs::text_to_image::TTIConfig::default();
+    let resp = transformrs::text_to_image::text_to_image(&key, config, "A sunset.")
+        .await
+        .unwrap();
+    let image = resp.structured().unwrap().images[0]
+        .base64_decode()
+        .unwrap();
+    assert_eq!(&image.image[..], b"png");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/image/generation "));
+}
+
+#[tokio::test]
+async fn text_to_image_deepinfra_no_image() {
+    let body = r#"{"images": [], "request_id": "1"}"#;
+    let (address, key, _requests) =
+        common::serve_key(Provider::DeepInfra, vec![common::response(200, body)]);
+    let config = transformrs::text_to_image::TTIConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let resp = transformrs::text_to_image::text_to_image(&key, config, "A sunset.")
+        .await
+        .unwrap();
+    let err = resp.structured().err().unwrap();
+    assert!(matches!(err, Error::Decode(_)));
+}
@@ -2,23 +2,23 @@ extern crate transformrs;
 
 mod common;
 
+use futures_util::stream::StreamExt;
 use serde_json::json;
 use std::collections::HashMap;
-use std::error::Error;
-use std::fs::File;
-use std::io::Write;
+use transformrs::text_to_speech::InputType;
 use transformrs::text_to_speech::Speech;
 use transformrs::text_to_speech::TTSConfig;
+use transformrs::Error;
 use transformrs::Provider;
 
 async fn tts_helper(
     provider: &Provider,
     config: &TTSConfig,
     model: Option<&str>,
-) -> Result<Speech, Box<dyn Error + Send + Sync>> {
+) -> Result<Speech, Error> {
     common::init_tracing();
     let keys = transformrs::load_keys(".env");
-    let key = keys.for_provider(&provider).unwrap();
+    let key = keys.for_provider(provider).unwrap();
     let msg = "Hello, world!";
     let resp = transformrs::text_to_speech::tts(&key, config, model, msg)
         .await
@@ -28,19 +28,22 @@ async fn tts_helper(
 
 #[tokio::test]
 async fn test_tts_deepinfra() {
-    let mut config = transformrs::text_to_speech::TTSConfig::default();
-    config.voice = Some("am_echo".to_string());
-    config.output_format = Some("mp3".to_string());
+    let config = transformrs::text_to_speech::TTSConfig {
+        voice: Some("am_echo".to_string()),
+        output_format: Some("mp3".to_string()),
+        ..Default::default()
+    };
     let model = Some("hexgrad/Kokoro-82M");
     let provider = Provider::DeepInfra;
     let speech = tts_helper(&provider, &config, model).await.unwrap();
     assert_eq!(speech.file_format, "mp3");
     let bytes = speech.audio.clone();
-    assert!(bytes.len() > 0);
+    assert!(!bytes.is_empty());
 
     // Can be used to manually verify the output.
-    let mut file = File::create("tests/tmp-deepinfra.mp3").unwrap();
-    file.write_all(&bytes).unwrap();
+    speech
+        .save_with_extension("tests", "tmp-deepinfra")
+        .unwrap();
 }
 
 #[tokio::test]
@@ -75,25 +78,40 @@ async fn test_tts_hyperbolic() {
     let model = None;
     let provider = Provider::Hyperbolic;
     let speech = tts_helper(&provider, &config, model).await.unwrap();
-    let mut file = File::create("tests/tmp").unwrap();
-    file.write_all(&speech.audio.clone()).unwrap();
     let bytes = speech.audio.clone();
-    assert!(bytes.len() > 0);
+    assert!(!bytes.is_empty());
 
     // Can be used to manually verify the output.
-    let mut file = File::create("tests/tmp-hyperbolic.mp3").unwrap();
-    file.write_all(&bytes).unwrap();
+    speech
+        .save_with_extension("tests", "tmp-hyperbolic")
+        .unwrap();
 }
 
 #[tokio::test]
 async fn test_tts_openai() {
-    let mut config = transformrs::text_to_speech::TTSConfig::default();
-    config.voice = Some("alloy".to_string());
+    let config = transformrs::text_to_speech::TTSConfig {
+        voice: Some("alloy".to_string()),
+        ..Default::default()
+    };
+    let model = Some("tts-1");
+    let provider = Provider::OpenAI;
+    let speech = tts_helper(&provider, &config, model).await.unwrap();
+    speech.save_with_extension("tests", "tmp-openai").unwrap();
+}
+
+#[tokio::test]
+async fn test_tts_openai_output_format() {
+    let config = TTSConfig {
+        voice: Some("alloy".to_string()),
+        output_format: Some("opus".to_string()),
+        ..Default::default()
+    };
     let model = Some("tts-1");
     let provider = Provider::OpenAI;
     let speech = tts_helper(&provider, &config, model).await.unwrap();
-    let mut file = File::create("tests/tmp-openai.mp3").unwrap();
-    file.write_all(&speech.audio.clone()).unwrap();
+    assert_eq!(speech.file_format, "opus");
+    // Ogg container, which is used for opus.
+    assert!(speech.audio.starts_with(b"OggS"));
 }
 
 #[tokio::test]
@@ -107,16 +125,123 @@ async fn test_tts_openai_error() {
     assert!(err.to_string().contains("model_not_found"));
 }
 
+#[tokio::test]
+async fn test_tts_stream_openai() {
+    common::init_tracing();
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&Provider::OpenAI).unwrap();
+    let config = TTSConfig {
+        voice: Some("alloy".to_string()),
+        ..Default::default()
+    };
+    let text = "Hello, world! This is a somewhat longer text to get multiple chunks.";
+    let mut stream = transformrs::text_to_speech::tts_stream(&key, &config, Some("tts-1"), text)
+        .await
+        .unwrap();
+    let mut audio = vec![];
+    while let Some(chunk) = stream.next().await {
+        audio.extend_from_slice(&chunk.unwrap());
+    }
+    assert!(!audio.is_empty());
+}
+
+#[tokio::test]
+async fn test_tts_stream_unsupported_provider() {
+    let key = transformrs::Key::new(Provider::Groq, "foo");
+    let config = TTSConfig::default();
+    let resp = transformrs::text_to_speech::tts_stream(&key, &config, None, "Hello").await;
+    assert!(matches!(
+        resp,
+        Err(Error::UnsupportedProvider(Provider::Groq))
+    ));
+}
+
+#[test]
+fn test_tts_elevenlabs_voice_settings() {
+    let key = transformrs::Key::new(Provider::ElevenLabs, "foo");
+    let mut other = HashMap::new();
+    other.insert("voice_settings".to_string(), json!({"stability": 0.5}));
+    let config = TTSConfig {
+        voice: Some("voice".to_string()),
+        speed: Some(1.1),
+        other: Some(other),
+        ..Default::default()
+    };
+    let model = Some("eleven_multilingual_v2");
+    let (_, _, body) =
+        transformrs::text_to_speech::build_tts_request(&key, &config, model, "Hi").unwrap();
+    assert_eq!(body["voice_settings"]["stability"], 0.5);
+    assert!((body["voice_settings"]["speed"].as_f64().unwrap() - 1.1).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_tts_elevenlabs() {
+    let mut other = HashMap::new();
+    other.insert(
+        "voice_settings".to_string(),
+        json!({"stability": 0.5, "similarity_boost": 0.75}),
+    );
+    let config = TTSConfig {
+        output_format: Some("mp3_44100_128".to_string()),
+        other: Some(other),
+        ..Default::default()
+    };
+    let model = Some("eleven_multilingual_v2");
+    let provider = Provider::ElevenLabs;
+    let speech = tts_helper(&provider, &config, model).await.unwrap();
+    assert_eq!(speech.file_format, "mp3");
+    assert!(!speech.audio.is_empty());
+    speech
+        .save_with_extension("tests", "tmp-elevenlabs")
+        .unwrap();
+}
+
 #[tokio::test]
 async fn test_tts_google() {
-    let mut config = transformrs::text_to_speech::TTSConfig::default();
-    config.voice = Some("en-US-Studio-Q".to_string());
-    config.language_code = Some("en-US".to_string());
+    let config = transformrs::text_to_speech::TTSConfig {
+        voice: Some("en-US-Studio-Q".to_string()),
+        language_code: Some("en-US".to_string()),
+        ..Default::default()
+    };
     let model = None;
     let provider = Provider::Google;
     let speech = tts_helper(&provider, &config, model).await.unwrap();
-    let mut file = File::create("tests/tmp-google.mp3").unwrap();
-    file.write_all(&speech.audio.clone()).unwrap();
+    speech.save_with_extension("tests", "tmp-google").unwrap();
+}
+
+#[tokio::test]
+async fn test_tts_google_ssml() {
+    common::init_tracing();
+    let mut other = HashMap::new();
+    other.insert("enableTimePointing".to_string(), json!(["SSML_MARK"]));
+    let config = TTSConfig {
+        voice: Some("en-US-Standard-A".to_string()),
+        language_code: Some("en-US".to_string()),
+        input_type: InputType::Ssml,
+        other: Some(other),
+        ..Default::default()
+    };
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&Provider::Google).unwrap();
+    let ssml = r#"<speak>Hello, <mark name="world"/>world!</speak>"#;
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, ssml)
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.timepoints.len(), 1);
+    assert_eq!(speech.timepoints[0].mark_name, "world");
+}
+
+#[tokio::test]
+async fn test_tts_ssml_unsupported() {
+    let key = transformrs::Key::new(Provider::OpenAI, "foo");
+    let config = TTSConfig {
+        input_type: InputType::Ssml,
+        ..Default::default()
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, None, "<speak>Hi</speak>").await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
 }
 
 #[tokio::test]
@@ -129,3 +254,773 @@ async fn test_tts_google_error() {
     println!("err: {}", err);
     assert!(err.to_string().contains("INVALID_ARGUMENT"));
 }
+
+#[tokio::test]
+async fn test_tts_unsupported_provider() {
+    let key = transformrs::Key::new(Provider::Groq, "foo");
+    let config = TTSConfig::default();
+    let resp = transformrs::text_to_speech::tts(&key, &config, None, "Hello, world!").await;
+    assert!(matches!(
+        resp,
+        Err(Error::UnsupportedProvider(Provider::Groq))
+    ));
+}
+
+#[tokio::test]
+async fn test_list_voices_google() {
+    let keys = transformrs::load_keys(".env");
+    let key = keys.for_provider(&Provider::Google).unwrap();
+    let voices = transformrs::text_to_speech::list_voices(&key)
+        .await
+        .unwrap();
+    let voice = voices.iter().find(|v| v.id == "en-US-Studio-Q").unwrap();
+    assert!(voice.language_codes.contains(&"en-US".to_string()));
+}
+
+#[tokio::test]
+async fn test_list_voices_openai() {
+    let key = transformrs::Key::new(Provider::OpenAI, "foo");
+    let voices = transformrs::text_to_speech::list_voices(&key)
+        .await
+        .unwrap();
+    assert!(voices.iter().any(|v| v.id == "alloy"));
+}
+
+#[test]
+fn test_base64_decode_data_uri() {
+    let provider = Provider::DeepInfra;
+    let bytes = Speech::base64_decode("data:audio/wav;base64,UklGRg==", &provider).unwrap();
+    assert_eq!(&bytes[..], b"RIFF");
+    let bytes = Speech::base64_decode("data:audio/mp3;base64,SUQz", &provider).unwrap();
+    assert_eq!(&bytes[..], b"ID3");
+    let err = Speech::base64_decode("SUQz", &provider).unwrap_err();
+    assert!(matches!(err, Error::Decode(_)));
+    let err = Speech::base64_decode("data:audio/;base64,SUQz", &provider).unwrap_err();
+    assert!(matches!(err, Error::Decode(_)));
+    let bytes = Speech::base64_decode("SUQz", &Provider::Hyperbolic).unwrap();
+    assert_eq!(&bytes[..], b"ID3");
+}
+
+#[test]
+fn test_speech_save_with_extension() {
+    let speech = Speech {
+        request_id: None,
+        file_format: "wav".to_string(),
+        audio: bytes::Bytes::from_static(b"RIFF"),
+        timepoints: vec![],
+        sample_rate: None,
+        duration_seconds: None,
+    };
+    let dir = std::env::temp_dir();
+    let path = speech
+        .save_with_extension(&dir, "transformrs-save")
+        .unwrap();
+    assert_eq!(path, dir.join("transformrs-save.wav"));
+    assert_eq!(std::fs::read(&path).unwrap(), b"RIFF");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_split_text() {
+    let text = "First sentence. Second sentence! Third one?";
+    let chunks = transformrs::text_to_speech::split_text(text, 35);
+    assert_eq!(
+        chunks,
+        vec!["First sentence. Second sentence!", "Third one?"]
+    );
+    let chunks = transformrs::text_to_speech::split_text("aaaa bbbb", 3);
+    assert_eq!(chunks, vec!["aaa", "a", "bbb", "b"]);
+    assert!(transformrs::text_to_speech::split_text("", 10).is_empty());
+}
+
+fn wav(samples: &[u8]) -> bytes::Bytes {
+    let mut out = vec![];
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&[1, 0, 1, 0, 0x80, 0x3e, 0, 0, 0, 0x7d, 0, 0, 2, 0, 16, 0]);
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+    out.extend_from_slice(samples);
+    bytes::Bytes::from(out)
+}
+
+fn speech(file_format: &str, audio: bytes::Bytes) -> Speech {
+    Speech {
+        request_id: None,
+        file_format: file_format.to_string(),
+        audio,
+        timepoints: vec![],
+        sample_rate: None,
+        duration_seconds: None,
+    }
+}
+
+#[test]
+fn test_concat_speech() {
+    use transformrs::text_to_speech::concat_speech;
+
+    let speeches = vec![speech("wav", wav(&[1, 2])), speech("wav", wav(&[3, 4]))];
+    let combined = concat_speech(speeches).unwrap();
+    assert_eq!(combined.audio, wav(&[1, 2, 3, 4]));
+
+    let a = bytes::Bytes::from_static(b"ID3a");
+    let b = bytes::Bytes::from_static(b"ID3b");
+    let combined = concat_speech(vec![speech("mp3", a), speech("mp3", b)]).unwrap();
+    assert_eq!(&combined.audio[..], b"ID3aID3b");
+
+    let a = bytes::Bytes::from_static(b"OggS");
+    let speeches = vec![speech("opus", a.clone()), speech("opus", a)];
+    let err = concat_speech(speeches).unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig(_)));
+}
+
+#[tokio::test]
+async fn test_tts_base_url() {
+    let html = "<html>Bad Gateway</html>";
+    let responses = vec![common::response(200, "ID3"), common::response(502, html)];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = TTSConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(&speech.audio[..], b"ID3");
+    assert!(requests.lock().unwrap()[0].starts_with("POST /v1/audio/speech"));
+
+    let err = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap_err();
+    match err {
+        Error::Api {
+            status, message, ..
+        } => {
+            assert_eq!(status, 502);
+            assert_eq!(message, html);
+        }
+        err => panic!("unexpected error: {err}"),
+    }
+}
+
+#[tokio::test]
+async fn test_tts_azure() {
+    let responses = vec![common::response(200, "ID3")];
+    let (address, key, requests) = common::serve_key(Provider::Azure, responses);
+    let config = TTSConfig {
+        voice: Some("alloy".to_string()),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, Some("tts"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "mp3");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /openai/deployments/tts/audio/speech?api-version="));
+    assert!(request.contains(r#""voice":"alloy""#));
+}
+
+#[tokio::test]
+async fn test_tts_google_audio_config() {
+    let body = r#"{"audioContent": "UklGRg=="}"#;
+    let responses = vec![common::response(200, body), common::response(200, body)];
+    let (address, key, requests) = common::serve_key(Provider::Google, responses);
+    let config = TTSConfig {
+        voice: Some("en-US-Studio-Q".to_string()),
+        speed: Some(1.5),
+        pitch: Some(-2.0),
+        volume_gain_db: Some(3.0),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "wav");
+    let request = requests.lock().unwrap()[0].clone();
+    // The key is sent in the address instead of a header.
+    assert!(request.starts_with("POST /v1beta1/text:synthesize?key=foo "));
+    assert!(!request.to_lowercase().contains("authorization:"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["audioConfig"]["audioEncoding"], "LINEAR16");
+    assert_eq!(body["audioConfig"]["pitch"], -2.0);
+    assert_eq!(body["audioConfig"]["speakingRate"], 1.5);
+    assert_eq!(body["audioConfig"]["volumeGainDb"], 3.0);
+    assert_eq!(body["voice"]["languageCode"], "en-US");
+    assert!(body.get("speed").is_none());
+
+    let config = TTSConfig {
+        voice: Some("cmn-CN-Wavenet-A".to_string()),
+        language_code: Some("yue-HK".to_string()),
+        ..config
+    };
+    transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[1].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["voice"]["languageCode"], "yue-HK");
+
+    let key = transformrs::Key {
+        provider: Provider::OpenAI,
+        ..key
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, None, "Hi").await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_tts_google_output_format() {
+    let body = r#"{"audioContent": "SUQz"}"#;
+    let responses = vec![common::response(200, body)];
+    let (address, key, requests) = common::serve_key(Provider::Google, responses);
+    let config = TTSConfig {
+        output_format: Some("mp3".to_string()),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "mp3");
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["audioConfig"]["audioEncoding"], "MP3");
+    assert!(body.get("output_format").is_none());
+
+    let config = TTSConfig {
+        output_format: Some("flac".to_string()),
+        ..config
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, None, "Hi").await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
+}
+
+#[test]
+fn test_tts_amazon_signature() {
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+    use transformrs::text_to_speech::build_tts_request_at;
+
+    // Credentials and time of the AWS Signature Version 4 test suite. The
+    // expected signatures were computed with `botocore.auth.SigV4Auth`.
+    let secret = "AKIDEXAMPLE:wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    let key = transformrs::Key::new(Provider::Amazon, secret);
+    let config = TTSConfig::default();
+    let time = UNIX_EPOCH + Duration::from_secs(1440938160);
+    let (url, headers, body) = build_tts_request_at(&key, &config, None, "Hello", time).unwrap();
+    assert_eq!(
+        url.as_str(),
+        "https://polly.us-east-1.amazonaws.com/v1/speech"
+    );
+    assert_eq!(
+        body.to_string(),
+        r#"{"OutputFormat":"mp3","Text":"Hello","TextType":"text","VoiceId":"Joanna"}"#
+    );
+    assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+    assert_eq!(
+        headers["authorization"],
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/polly/aws4_request, \
+         SignedHeaders=content-type;host;x-amz-date, \
+         Signature=054d9e4dc1422c125f019c2bc02c727f0e96fdd9eeb96da52a0c33be8a10fe86"
+    );
+
+    let key = transformrs::Key::new(Provider::Amazon, &format!("{secret}:TOKEN"));
+    let (_, headers, _) = build_tts_request_at(&key, &config, None, "Hello", time).unwrap();
+    assert_eq!(headers["x-amz-security-token"], "TOKEN");
+    assert_eq!(
+        headers["authorization"],
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/polly/aws4_request, \
+         SignedHeaders=content-type;host;x-amz-date;x-amz-security-token, \
+         Signature=3e011833360e0cfc2eec27e93eb8bce25ff3298f85bc53e312dca35c59e8f835"
+    );
+
+    let dates = [
+        (0, "19700101T000000Z"),
+        (951868800, "20000301T000000Z"),
+        (1709251199, "20240229T235959Z"),
+        (4107542400, "21000301T000000Z"),
+    ];
+    for (secs, expected) in dates {
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        let (_, headers, _) = build_tts_request_at(&key, &config, None, "Hello", time).unwrap();
+        assert_eq!(headers["x-amz-date"], expected);
+    }
+}
+
+#[test]
+fn test_tts_amazon_extra_headers() {
+    use transformrs::text_to_speech::build_tts_request;
+
+    let key = transformrs::Key::new(Provider::Amazon, "AKIDEXAMPLE:secret");
+    let mut extra_headers = HashMap::new();
+    extra_headers.insert("X-Trace".to_string(), "1".to_string());
+    let config = TTSConfig {
+        extra_headers: Some(extra_headers.clone()),
+        ..Default::default()
+    };
+    let (_, headers, _) = build_tts_request(&key, &config, None, "Hi").unwrap();
+    assert_eq!(headers["x-trace"], "1");
+
+    for name in ["Content-Type", "X-Amz-Date", "Host"] {
+        let mut extra_headers = extra_headers.clone();
+        extra_headers.insert(name.to_string(), "foo".to_string());
+        let config = TTSConfig {
+            extra_headers: Some(extra_headers),
+            ..Default::default()
+        };
+        let result = build_tts_request(&key, &config, None, "Hi");
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+}
+
+#[tokio::test]
+async fn test_tts_amazon() {
+    let responses = vec![common::response(200, "ID3")];
+    let (address, requests) = common::serve(responses);
+    let key = transformrs::Key::new(Provider::Amazon, "AKIDEXAMPLE:secret");
+    let config = TTSConfig {
+        voice: Some("Matthew".to_string()),
+        output_format: Some("ogg".to_string()),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, Some("neural"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "ogg");
+    assert_eq!(speech.audio, "ID3");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/speech"));
+    let (headers, body) = request.split_once("\r\n\r\n").unwrap();
+    let headers = headers.to_lowercase();
+    assert!(headers.contains("authorization: aws4-hmac-sha256 credential=akidexample/"));
+    assert!(headers.contains("/us-east-1/polly/aws4_request"));
+    assert!(headers.contains("signedheaders=content-type;host;x-amz-date"));
+    assert!(headers.contains("x-amz-date: "));
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["VoiceId"], "Matthew");
+    assert_eq!(body["OutputFormat"], "ogg_vorbis");
+    assert_eq!(body["Engine"], "neural");
+
+    let key = transformrs::Key {
+        key: "foo".to_string(),
+        ..key
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, None, "Hi").await;
+    assert!(matches!(resp, Err(Error::InvalidConfig(_))));
+}
+
+#[tokio::test]
+async fn test_tts_default_model() {
+    let responses = vec![common::response(200, "{}")];
+    let (address, key, requests) = common::serve_key(Provider::DeepInfra, responses);
+    let config = TTSConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap();
+    let model = Provider::DeepInfra.default_tts_model().unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with(&format!("POST /v1/inference/{model} ")));
+    assert_eq!(Provider::Google.default_tts_model(), None);
+}
+
+#[tokio::test]
+async fn test_tts_speed_range() {
+    let responses = vec![common::response(200, "ID3")];
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = TTSConfig {
+        speed: Some(5.0),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let resp = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi").await;
+    let err = resp.err().unwrap();
+    assert!(matches!(err, Error::InvalidConfig(_)));
+    assert!(err.to_string().contains("0.25 to 4"));
+
+    let config = TTSConfig {
+        clamp_speed: true,
+        ..config
+    };
+    transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .unwrap();
+    let request = requests.lock().unwrap()[0].clone();
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["speed"], 4.0);
+}
+
+#[tokio::test]
+async fn test_tts_together() {
+    let responses = vec![common::response(200, "RIFF")];
+    let (address, key, requests) = common::serve_key(Provider::TogetherAI, responses);
+    let config = TTSConfig {
+        voice: Some("laidback woman".to_string()),
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let model = Provider::TogetherAI.default_tts_model();
+    let speech = transformrs::text_to_speech::tts(&key, &config, model, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "wav");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/audio/speech"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["input"], "Hi");
+    assert_eq!(body["voice"], "laidback woman");
+    assert_eq!(body["model"], "cartesia/sonic");
+}
+
+#[tokio::test]
+async fn test_tts_deepinfra_detail() {
+    let array = r#"{"detail":[{"loc":["body","text"],"msg":"field required","type":"value_error.missing"}]}"#;
+    let responses = vec![
+        common::response(422, array),
+        common::response(400, r#"{"detail":"model not found"}"#),
+    ];
+    let (address, key, _requests) = common::serve_key(Provider::DeepInfra, responses);
+    let config = TTSConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let model = Provider::DeepInfra.default_tts_model();
+    let err = transformrs::text_to_speech::tts(&key, &config, model, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Api { status: 422, .. }));
+    assert!(err.to_string().contains("body.text: field required"));
+
+    let err = transformrs::text_to_speech::tts(&key, &config, model, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("model not found"));
+    assert!(!err.to_string().contains('"'));
+}
+
+#[test]
+fn test_build_tts_request() {
+    let key = transformrs::Key::new(Provider::Google, "foo");
+    let config = TTSConfig::default();
+    let (url, headers, body) =
+        transformrs::text_to_speech::build_tts_request(&key, &config, None, "Hi").unwrap();
+    assert_eq!(url.path(), "/v1beta1/text:synthesize");
+    assert!(headers.get("Authorization").is_none());
+    assert_eq!(body["input"]["text"], "Hi");
+}
+
+#[test]
+fn test_tts_config_builder() {
+    let config = TTSConfig::builder()
+        .voice("alloy")
+        .speed(1.2)
+        .output_format("opus")
+        .input_type(InputType::Ssml)
+        .build();
+    assert_eq!(config.voice.as_deref(), Some("alloy"));
+    assert_eq!(config.speed, Some(1.2));
+    assert_eq!(config.output_format.as_deref(), Some("opus"));
+    assert_eq!(config.input_type, InputType::Ssml);
+    assert_eq!(config.language_code, None);
+    assert!(!config.clamp_speed);
+}
+
+#[tokio::test]
+async fn test_speech_duration() {
+    use base64::prelude::*;
+    let audio = BASE64_STANDARD.encode(wav(&[0; 32000]));
+    let body = format!(r#"{{"audioContent": "{audio}"}}"#);
+    let pcm = "\0".repeat(16000);
+    let responses = vec![common::response(200, &body), common::response(200, &pcm)];
+    let (address, key, _requests) = common::serve_key(Provider::Google, responses);
+    let config = TTSConfig {
+        base_url: Some(address),
+        ..Default::default()
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.sample_rate, Some(16000));
+    assert_eq!(speech.duration_seconds, Some(1.0));
+
+    let key = transformrs::Key {
+        provider: Provider::ElevenLabs,
+        ..key
+    };
+    let config = TTSConfig {
+        output_format: Some("pcm_16000".to_string()),
+        ..config
+    };
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "pcm");
+    assert_eq!(speech.sample_rate, Some(16000));
+    assert_eq!(speech.duration_seconds, Some(0.5));
+}
+
+#[tokio::test]
+async fn test_tts_hyperbolic_voice() {
+    let responses = vec![common::response(200, r#"{"audio": "SUQz"}"#)];
+    let (address, key, requests) = common::serve_key(Provider::Hyperbolic, responses);
+    let config = TTSConfig::builder()
+        .voice("EN-BR")
+        .speed(1.5)
+        .base_url(&address)
+        .build();
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(&speech.audio[..], b"ID3");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.starts_with("POST /v1/audio/generation"));
+    let body = request.split("\r\n\r\n").nth(1).unwrap();
+    let body = serde_json::from_str::<serde_json::Value>(body).unwrap();
+    assert_eq!(body["speaker"], "EN-BR");
+    assert_eq!(body["language"], "EN");
+    assert_eq!(body["speed"], 1.5);
+
+    let voices = transformrs::text_to_speech::list_voices(&key)
+        .await
+        .unwrap();
+    assert!(voices.iter().any(|voice| voice.id == "EN-US"));
+}
+
+#[test]
+fn test_tts_nebius_novita_unsupported() {
+    for provider in [Provider::Nebius, Provider::Novita] {
+        let key = transformrs::Key::new(provider, "foo");
+        let config = TTSConfig::default();
+        let result = transformrs::text_to_speech::build_tts_request(&key, &config, None, "Hi");
+        assert!(matches!(result, Err(Error::UnsupportedProvider(_))));
+    }
+}
+
+#[test]
+fn test_speech_to_wav() {
+    let pcm = bytes::Bytes::from(vec![0; 48000]);
+    let wav = speech("pcm", pcm).to_wav(24000, 1);
+    assert_eq!(&wav[..4], b"RIFF");
+    assert_eq!(&wav[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 24000);
+    assert_eq!(u32::from_le_bytes(wav[28..32].try_into().unwrap()), 48000);
+    assert_eq!(wav.len(), 44 + 48000);
+    // Audio that is already WAV is not wrapped again.
+    let speech = speech("wav", wav.clone());
+    assert_eq!(speech.to_wav(16000, 2), wav);
+}
+
+#[tokio::test]
+async fn test_tts_google_linear16_header() {
+    use base64::prelude::*;
+    let audio = BASE64_STANDARD.encode([0; 4800]);
+    let body = format!(r#"{{"audioContent": "{audio}"}}"#);
+    let (address, key, _requests) =
+        common::serve_key(Provider::Google, vec![common::response(200, &body)]);
+    let config = TTSConfig::builder().base_url(&address).build();
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(&speech.audio[..4], b"RIFF");
+    assert_eq!(speech.sample_rate, Some(24000));
+    assert_eq!(speech.duration_seconds, Some(0.1));
+}
+
+#[tokio::test]
+async fn test_tts_partial_audio() {
+    let truncated =
+        "HTTP/1.1 200 OK\r\nContent-Type: audio/mpeg\r\nContent-Length: 100\r\nConnection: close\r\n\r\nID3"
+            .to_string();
+    let responses = vec![
+        truncated,
+        common::response(200, r#"{"audio": "data:audio/mp3;base64,S"}"#),
+        common::response(200, r#"{"audio": 1}"#),
+    ];
+    let (address, key, _requests) = common::serve_key(Provider::OpenAI, responses);
+    let config = TTSConfig::builder().base_url(&address).build();
+    let err = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .err()
+        .unwrap();
+    match err {
+        Error::PartialAudio { bytes, .. } => assert_eq!(&bytes[..], b"ID3"),
+        err => panic!("expected partial audio, got {err}"),
+    }
+
+    let key = transformrs::Key {
+        provider: Provider::DeepInfra,
+        ..key
+    };
+    let err = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .err()
+        .unwrap();
+    match err {
+        Error::PartialAudio { bytes, source } => {
+            assert!(bytes.starts_with(b"{\"audio\""));
+            assert!(matches!(*source, Error::Decode(_)));
+        }
+        err => panic!("expected partial audio, got {err}"),
+    }
+
+    // Only the audio itself is partial, not a response without audio.
+    let err = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Decode(_)));
+}
+
+#[tokio::test]
+async fn test_tts_content_type() {
+    let audio =
+        "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: 4\r\nConnection: close\r\n\r\nRIFF"
+            .to_string();
+    let responses = vec![audio, common::response(200, r#"{"status": "queued"}"#)];
+    let (address, _requests) = common::serve(responses);
+    // DeepInfra usually returns base64 in JSON.
+    let key = transformrs::Key::new(Provider::DeepInfra, "foo");
+    let config = TTSConfig::builder().base_url(&address).build();
+    let speech = transformrs::text_to_speech::tts(&key, &config, None, "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "wav");
+    assert_eq!(&speech.audio[..], b"RIFF");
+
+    // OpenAI usually returns the audio itself.
+    let key = transformrs::Key {
+        provider: Provider::OpenAI,
+        ..key
+    };
+    let err = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("expected audio but received JSON"));
+}
+
+#[tokio::test]
+async fn test_tts_json_audio() {
+    let responses = vec![common::response(
+        200,
+        r#"{"audio": "data:audio/wav;base64,UklGRg==", "request_id": "1"}"#,
+    )];
+    let (address, _requests) = common::serve(responses);
+    // OpenAI-compatible servers may return base64 in JSON too.
+    let key = transformrs::Key::new(Provider::OpenAI, "foo");
+    let config = TTSConfig::builder().base_url(&address).build();
+    let speech = transformrs::text_to_speech::tts(&key, &config, Some("tts-1"), "Hi")
+        .await
+        .unwrap()
+        .structured()
+        .unwrap();
+    assert_eq!(speech.file_format, "wav");
+    assert_eq!(&speech.audio[..], b"RIFF");
+    assert_eq!(speech.request_id.as_deref(), Some("1"));
+}
+
+#[test]
+fn test_voice_catalogs() {
+    use transformrs::text_to_speech::deepinfra_voices;
+    use transformrs::text_to_speech::hyperbolic_voices;
+    use transformrs::text_to_speech::openai_voices;
+
+    let ids = openai_voices()
+        .iter()
+        .map(|v| v.id.as_str())
+        .collect::<Vec<_>>();
+    for id in ["alloy", "echo", "fable", "onyx", "nova", "shimmer"] {
+        assert!(ids.contains(&id));
+    }
+    let bella = deepinfra_voices()
+        .iter()
+        .find(|v| v.id == "af_bella")
+        .unwrap();
+    assert_eq!(bella.gender.as_deref(), Some("female"));
+    assert!(hyperbolic_voices().iter().any(|v| v.id == "EN-US"));
+}
+
+#[test]
+fn test_base64_decode_url_safe() {
+    // Standard base64 would be "+/8=".
+    let audio = Speech::base64_decode("-_8=", &Provider::Hyperbolic).unwrap();
+    assert_eq!(&audio[..], &[0xFB, 0xFF]);
+    let audio = Speech::base64_decode("-_8", &Provider::Hyperbolic).unwrap();
+    assert_eq!(&audio[..], &[0xFB, 0xFF]);
+    let err = Speech::base64_decode("not base64!", &Provider::Hyperbolic)
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::Decode(_)));
+}
+
+#[tokio::test]
+async fn test_tts_formats() {
+    let audio =
+        "HTTP/1.1 200 OK\r\nContent-Type: audio/wav\r\nContent-Length: 4\r\nConnection: close\r\n\r\nRIFF"
+            .to_string();
+    let (address, key, requests) = common::serve_key(Provider::OpenAI, vec![audio]);
+    let config = TTSConfig::builder().base_url(&address).build();
+    let speeches =
+        transformrs::text_to_speech::tts_formats(&key, &config, Some("tts-1"), "Hi", &["wav"])
+            .await
+            .unwrap();
+    assert_eq!(speeches["wav"].file_format, "wav");
+    let request = requests.lock().unwrap()[0].clone();
+    assert!(request.contains(r#""response_format":"wav""#));
+
+    let formats = ["mp3", "pcm"];
+    let err = transformrs::text_to_speech::tts_formats(&key, &config, None, "Hi", &formats)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::InvalidConfig(_)));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}